@@ -0,0 +1,477 @@
+// Copyright 2019 Red Hat
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The readiness checks behind `sev-detect`.
+//!
+//! Nothing in here touches the machine directly: files are resolved relative
+//! to a root directory, and CPUID and the SEV firmware are reached through
+//! the `Cpuid` and `Firmware` traits. This lets the checks run against a fake
+//! `/dev` and `/sys` tree with canned CPUID values.
+
+use std::fmt::{self, Display, Formatter};
+use std::fs::{self, OpenOptions};
+use std::path::Path;
+
+/// The vendor string reported by CPUID leaf 0 on AMD processors.
+const AMD_VENDOR: &[u8; 12] = b"AuthenticAMD";
+
+/// The highest extended CPUID leaf is reported by this leaf.
+const EXTENDED_MAX_LEAF: u32 = 0x8000_0000;
+
+/// The AMD memory encryption capabilities leaf.
+const SEV_LEAF: u32 = 0x8000_001f;
+
+/// The register values returned by a single CPUID invocation.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Leaf {
+    pub eax: u32,
+    pub ebx: u32,
+    pub ecx: u32,
+    pub edx: u32,
+}
+
+/// A source of CPUID results.
+pub trait Cpuid {
+    fn cpuid(&self, leaf: u32) -> Leaf;
+}
+
+/// What the SEV firmware reports about the platform.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FirmwareStatus {
+    /// The firmware API version and build.
+    pub version: String,
+
+    /// The number of guests currently using an SEV ASID.
+    pub guests: u32,
+}
+
+/// A way of querying the SEV firmware.
+pub trait Firmware {
+    fn status(&self) -> Result<FirmwareStatus, String>;
+}
+
+/// The result of a single check.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Pass(String),
+    Fail(String),
+
+    /// The check could not be performed because an earlier check failed.
+    Skip(String),
+}
+
+/// A named check and its outcome.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Check {
+    pub name: &'static str,
+    pub outcome: Outcome,
+}
+
+/// The complete readiness report.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Report {
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    /// The platform is ready to launch SEV guests if no check failed or was skipped.
+    pub fn ready(&self) -> bool {
+        self.checks
+            .iter()
+            .all(|c| matches!(c.outcome, Outcome::Pass(_)))
+    }
+
+    fn push(&mut self, name: &'static str, outcome: Outcome) -> bool {
+        let passed = matches!(outcome, Outcome::Pass(_));
+        self.checks.push(Check { name, outcome });
+        passed
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for check in self.checks.iter() {
+            let (tag, msg) = match &check.outcome {
+                Outcome::Pass(msg) => ("  OK", msg),
+                Outcome::Fail(msg) => ("FAIL", msg),
+                Outcome::Skip(msg) => ("SKIP", msg),
+            };
+            writeln!(f, "[{}] {}: {}", tag, check.name, msg)?;
+        }
+
+        if self.ready() {
+            writeln!(f, "\nThis platform is ready to run SEV guests.")
+        } else {
+            writeln!(f, "\nThis platform is NOT ready to run SEV guests.")
+        }
+    }
+}
+
+/// Runs every check, resolving device and sysfs paths relative to `root`.
+pub fn run(root: &Path, cpuid: &dyn Cpuid, firmware: &dyn Firmware) -> Report {
+    let mut report = Report::default();
+
+    let sev = check_cpu(&mut report, cpuid);
+    check_device(&mut report, "KVM device", &root.join("dev/kvm"));
+    check_module_param(&mut report, root);
+    let dev = check_device(&mut report, "SEV device", &root.join("dev/sev"));
+
+    let status = if dev {
+        match firmware.status() {
+            Ok(status) => {
+                report.push(
+                    "SEV firmware",
+                    Outcome::Pass(format!("API version {}", status.version)),
+                );
+                Some(status)
+            }
+            Err(e) => {
+                report.push("SEV firmware", Outcome::Fail(e));
+                None
+            }
+        }
+    } else {
+        report.push(
+            "SEV firmware",
+            Outcome::Skip("the SEV device is not accessible".into()),
+        );
+        None
+    };
+
+    let outcome = match (sev, status) {
+        (Some(leaf), Some(status)) => {
+            let asids = sev_asids(&leaf);
+            if asids == 0 {
+                Outcome::Fail("no ASIDs are reserved for SEV guests".into())
+            } else if status.guests < asids {
+                Outcome::Pass(format!(
+                    "{} of {} SEV ASIDs free",
+                    asids - status.guests,
+                    asids
+                ))
+            } else {
+                Outcome::Fail(format!("all {} SEV ASIDs are in use", asids))
+            }
+        }
+        (None, _) => Outcome::Skip("the CPU does not support SEV".into()),
+        (_, None) => Outcome::Skip("the SEV firmware status is unknown".into()),
+    };
+    report.push("ASID availability", outcome);
+
+    report
+}
+
+/// The number of ASIDs usable by (non-ES) SEV guests. ASIDs below the
+/// minimum SEV ASID are reserved for SEV-ES guests.
+fn sev_asids(leaf: &Leaf) -> u32 {
+    let min = leaf.edx.max(1);
+    (leaf.ecx + 1).saturating_sub(min)
+}
+
+/// Checks the CPU vendor and the SEV CPUID leaf. Returns the SEV leaf if the
+/// CPU supports SEV.
+fn check_cpu(report: &mut Report, cpuid: &dyn Cpuid) -> Option<Leaf> {
+    let vendor = cpuid.cpuid(0);
+    let mut name = [0u8; 12];
+    name[0..4].copy_from_slice(&vendor.ebx.to_le_bytes());
+    name[4..8].copy_from_slice(&vendor.edx.to_le_bytes());
+    name[8..12].copy_from_slice(&vendor.ecx.to_le_bytes());
+    let name = String::from_utf8_lossy(&name).into_owned();
+
+    if name.as_bytes() != AMD_VENDOR {
+        report.push("AMD CPU", Outcome::Fail(format!("vendor is {}", name)));
+        report.push("SEV CPUID leaf", Outcome::Skip("not an AMD CPU".into()));
+        report.push("SEV support", Outcome::Skip("not an AMD CPU".into()));
+        return None;
+    }
+    report.push("AMD CPU", Outcome::Pass(name));
+
+    let max = cpuid.cpuid(EXTENDED_MAX_LEAF).eax;
+    if max < SEV_LEAF {
+        report.push(
+            "SEV CPUID leaf",
+            Outcome::Fail(format!("highest extended leaf is {:#x}", max)),
+        );
+        report.push(
+            "SEV support",
+            Outcome::Skip("the SEV CPUID leaf is missing".into()),
+        );
+        return None;
+    }
+    report.push(
+        "SEV CPUID leaf",
+        Outcome::Pass(format!("highest extended leaf is {:#x}", max)),
+    );
+
+    let leaf = cpuid.cpuid(SEV_LEAF);
+    let sme = leaf.eax & (1 << 0) != 0;
+    let sev = leaf.eax & (1 << 1) != 0;
+    let es = leaf.eax & (1 << 3) != 0;
+    if !sev {
+        report.push("SEV support", Outcome::Fail("SEV is not supported".into()));
+        return None;
+    }
+
+    report.push(
+        "SEV support",
+        Outcome::Pass(format!(
+            "SME: {}, SEV-ES: {}, C-bit: {}, {} encrypted guests, minimum SEV ASID {}",
+            yes_no(sme),
+            yes_no(es),
+            leaf.ebx & 0x3f,
+            leaf.ecx,
+            leaf.edx,
+        )),
+    );
+
+    Some(leaf)
+}
+
+/// Checks that a device node can be opened for reading and writing.
+fn check_device(report: &mut Report, name: &'static str, path: &Path) -> bool {
+    let outcome = match OpenOptions::new().read(true).write(true).open(path) {
+        Ok(_) => Outcome::Pass(format!("{} is accessible", path.display())),
+        Err(e) => Outcome::Fail(format!("{}: {}", path.display(), e)),
+    };
+    report.push(name, outcome)
+}
+
+/// Checks that the `kvm_amd` module was loaded with SEV enabled.
+fn check_module_param(report: &mut Report, root: &Path) -> bool {
+    let path = root.join("sys/module/kvm_amd/parameters/sev");
+    let outcome = match fs::read_to_string(&path) {
+        Ok(value) => match value.trim() {
+            "1" | "Y" | "y" => Outcome::Pass("SEV is enabled in kvm_amd".into()),
+            other => Outcome::Fail(format!("kvm_amd sev={}; reload kvm_amd with sev=1", other)),
+        },
+        Err(e) => Outcome::Fail(format!("{}: {}; is kvm_amd loaded?", path.display(), e)),
+    };
+    report.push("kvm_amd sev parameter", outcome)
+}
+
+fn yes_no(value: bool) -> &'static str {
+    if value {
+        "yes"
+    } else {
+        "no"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// A fake `/dev` and `/sys` tree, removed when dropped.
+    struct Root(PathBuf);
+
+    impl Root {
+        fn new(name: &str, sev_device: bool, sev_param: Option<&str>) -> Self {
+            let root =
+                std::env::temp_dir().join(format!("sev-detect-{}-{}", std::process::id(), name));
+            let _ = fs::remove_dir_all(&root);
+            fs::create_dir_all(root.join("dev")).unwrap();
+            fs::write(root.join("dev/kvm"), b"").unwrap();
+            if sev_device {
+                fs::write(root.join("dev/sev"), b"").unwrap();
+            }
+            if let Some(value) = sev_param {
+                let params = root.join("sys/module/kvm_amd/parameters");
+                fs::create_dir_all(&params).unwrap();
+                fs::write(params.join("sev"), value).unwrap();
+            }
+            Root(root)
+        }
+    }
+
+    impl Drop for Root {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// An AMD CPU with 15 SEV ASIDs, from 1, which supports SEV or not.
+    struct FakeCpu {
+        sev: bool,
+    }
+
+    impl Cpuid for FakeCpu {
+        fn cpuid(&self, leaf: u32) -> Leaf {
+            let word = |bytes: &[u8]| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            match leaf {
+                0 => Leaf {
+                    ebx: word(&AMD_VENDOR[0..4]),
+                    edx: word(&AMD_VENDOR[4..8]),
+                    ecx: word(&AMD_VENDOR[8..12]),
+                    ..Default::default()
+                },
+                EXTENDED_MAX_LEAF => Leaf {
+                    eax: SEV_LEAF,
+                    ..Default::default()
+                },
+                SEV_LEAF => Leaf {
+                    eax: if self.sev { 0b11 } else { 0b01 },
+                    ebx: 47,
+                    ecx: 15,
+                    edx: 1,
+                },
+                _ => Leaf::default(),
+            }
+        }
+    }
+
+    struct FakeFirmware(Result<FirmwareStatus, String>);
+
+    impl Firmware for FakeFirmware {
+        fn status(&self) -> Result<FirmwareStatus, String> {
+            self.0.clone()
+        }
+    }
+
+    fn firmware(guests: u32) -> FakeFirmware {
+        FakeFirmware(Ok(FirmwareStatus {
+            version: "0.17 build 11".into(),
+            guests,
+        }))
+    }
+
+    fn outcome<'a>(report: &'a Report, name: &str) -> &'a Outcome {
+        &report
+            .checks
+            .iter()
+            .find(|check| check.name == name)
+            .unwrap_or_else(|| panic!("no {} check", name))
+            .outcome
+    }
+
+    fn names(report: &Report) -> Vec<&'static str> {
+        report.checks.iter().map(|check| check.name).collect()
+    }
+
+    #[test]
+    fn ready_platform() {
+        let root = Root::new("ready", true, Some("1\n"));
+        let report = run(&root.0, &FakeCpu { sev: true }, &firmware(3));
+
+        assert_eq!(
+            names(&report),
+            [
+                "AMD CPU",
+                "SEV CPUID leaf",
+                "SEV support",
+                "KVM device",
+                "kvm_amd sev parameter",
+                "SEV device",
+                "SEV firmware",
+                "ASID availability",
+            ]
+        );
+        assert_eq!(
+            outcome(&report, "SEV support"),
+            &Outcome::Pass(
+                "SME: yes, SEV-ES: no, C-bit: 47, 15 encrypted guests, minimum SEV ASID 1".into()
+            )
+        );
+        assert_eq!(
+            outcome(&report, "SEV firmware"),
+            &Outcome::Pass("API version 0.17 build 11".into())
+        );
+        assert_eq!(
+            outcome(&report, "ASID availability"),
+            &Outcome::Pass("12 of 15 SEV ASIDs free".into())
+        );
+        assert!(report.ready());
+    }
+
+    #[test]
+    fn cpu_without_sev() {
+        let root = Root::new("no-sev-cpu", true, Some("1"));
+        let report = run(&root.0, &FakeCpu { sev: false }, &firmware(0));
+
+        assert_eq!(
+            outcome(&report, "SEV support"),
+            &Outcome::Fail("SEV is not supported".into())
+        );
+        assert_eq!(
+            outcome(&report, "ASID availability"),
+            &Outcome::Skip("the CPU does not support SEV".into())
+        );
+        assert!(!report.ready());
+    }
+
+    #[test]
+    fn missing_sev_device() {
+        let root = Root::new("no-sev-device", false, Some("1"));
+        let report = run(&root.0, &FakeCpu { sev: true }, &firmware(0));
+
+        assert!(matches!(outcome(&report, "SEV device"), Outcome::Fail(_)));
+        assert!(matches!(outcome(&report, "KVM device"), Outcome::Pass(_)));
+        assert_eq!(
+            outcome(&report, "SEV firmware"),
+            &Outcome::Skip("the SEV device is not accessible".into())
+        );
+        assert_eq!(
+            outcome(&report, "ASID availability"),
+            &Outcome::Skip("the SEV firmware status is unknown".into())
+        );
+        assert!(!report.ready());
+    }
+
+    #[test]
+    fn kvm_amd_without_sev() {
+        let root = Root::new("sev-disabled", true, Some("0\n"));
+        let report = run(&root.0, &FakeCpu { sev: true }, &firmware(0));
+        assert_eq!(
+            outcome(&report, "kvm_amd sev parameter"),
+            &Outcome::Fail("kvm_amd sev=0; reload kvm_amd with sev=1".into())
+        );
+        assert!(!report.ready());
+
+        let root = Root::new("no-kvm-amd", true, None);
+        let report = run(&root.0, &FakeCpu { sev: true }, &firmware(0));
+        assert!(matches!(
+            outcome(&report, "kvm_amd sev parameter"),
+            Outcome::Fail(msg) if msg.ends_with("is kvm_amd loaded?")
+        ));
+        assert!(!report.ready());
+    }
+
+    #[test]
+    fn failing_firmware() {
+        let root = Root::new("firmware-fails", true, Some("Y"));
+        let firmware = FakeFirmware(Err("unable to open /dev/sev: EBUSY".into()));
+        let report = run(&root.0, &FakeCpu { sev: true }, &firmware);
+
+        assert_eq!(
+            outcome(&report, "SEV firmware"),
+            &Outcome::Fail("unable to open /dev/sev: EBUSY".into())
+        );
+        assert_eq!(
+            outcome(&report, "ASID availability"),
+            &Outcome::Skip("the SEV firmware status is unknown".into())
+        );
+        assert!(!report.ready());
+    }
+
+    #[test]
+    fn all_asids_in_use() {
+        let root = Root::new("asids-in-use", true, Some("1"));
+        let report = run(&root.0, &FakeCpu { sev: true }, &firmware(15));
+        assert_eq!(
+            outcome(&report, "ASID availability"),
+            &Outcome::Fail("all 15 SEV ASIDs are in use".into())
+        );
+        assert!(!report.ready());
+    }
+}
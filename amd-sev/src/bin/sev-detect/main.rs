@@ -0,0 +1,62 @@
+// Copyright 2019 Red Hat
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! `sev-detect` reports whether this machine can run the SEV demo: that the
+//! CPU supports SEV, that KVM and the SEV firmware are reachable, and that
+//! there is a free ASID for another encrypted guest.
+
+mod checks;
+
+use ketuvim::sev::sev::firmware::Firmware;
+use std::path::Path;
+
+/// Probes the machine this program runs on.
+struct Native;
+
+impl checks::Cpuid for Native {
+    // `__cpuid` is only `unsafe` on older toolchains.
+    #[allow(unused_unsafe)]
+    fn cpuid(&self, leaf: u32) -> checks::Leaf {
+        let result = unsafe { std::arch::x86_64::__cpuid(leaf) };
+        checks::Leaf {
+            eax: result.eax,
+            ebx: result.ebx,
+            ecx: result.ecx,
+            edx: result.edx,
+        }
+    }
+}
+
+impl checks::Firmware for Native {
+    fn status(&self) -> Result<checks::FirmwareStatus, String> {
+        let fw = Firmware::open().map_err(|e| format!("unable to open /dev/sev: {}", e))?;
+        let status = fw
+            .platform_status()
+            .map_err(|e| format!("unable to query platform status: {:?}", e))?;
+
+        Ok(checks::FirmwareStatus {
+            version: status.build.to_string(),
+            guests: status.guests,
+        })
+    }
+}
+
+fn main() {
+    let report = checks::run(Path::new("/"), &Native, &Native);
+    print!("{}", report);
+
+    if !report.ready() {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,232 @@
+// Copyright 2019 Red Hat
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The guest-to-host hypercall ABI.
+//!
+//! SEV encrypts guest memory, so the host cannot read arguments out of guest
+//! pages. Guest registers are not encrypted (this is SEV, not SEV-ES), so all
+//! arguments and return values travel in registers instead. The calling
+//! convention follows the i386 Linux system call ABI, except that `%edx` is
+//! taken up by the port number:
+//!
+//! | Register | Meaning                    |
+//! |----------|----------------------------|
+//! | `%rax`   | hypercall number           |
+//! | `%rbx`   | first argument             |
+//! | `%rcx`   | second argument            |
+//! | `%rsi`   | third argument             |
+//! | `%rdi`   | fourth argument            |
+//! | `%rdx`   | `PORT`                     |
+//!
+//! The guest triggers the hypercall by writing any byte to `PORT`, i.e.
+//! `out %al, (%dx)`. When the guest resumes, `%rax` holds the return value,
+//! or `ENOSYS` if no handler is registered for the hypercall number. A guest
+//! running in real mode can reach the 32-bit registers with an operand-size
+//! prefix; the upper halves are then simply ignored.
+
+use ketuvim::arch;
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The I/O port the guest writes to in order to issue a hypercall.
+pub const PORT: u16 = 0x0500;
+
+/// The value returned in `%rax` for an unknown hypercall number (`-1`).
+pub const ENOSYS: u64 = !0;
+
+/// Stops the guest. The first argument is the guest's result.
+pub const EXIT: u64 = 0;
+
+/// Returns the host's wall-clock time in seconds since the UNIX epoch.
+pub const TIME: u64 = 1;
+
+/// Logs a structured message. The arguments are the severity (see `Level`),
+/// a guest-defined message code and two guest-defined values.
+pub const LOG: u64 = 2;
+
+/// What the run loop should do once a hypercall has been handled.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    /// Resume the guest with the given value in `%rax`.
+    Return(u64),
+
+    /// Stop running the guest, which produced the given result.
+    Exit(u64),
+}
+
+/// The severity of a message logged with the `LOG` hypercall.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl Level {
+    /// Decodes the severity argument of a `LOG` hypercall.
+    pub fn from_arg(arg: u64) -> Option<Self> {
+        match arg {
+            0 => Some(Level::Error),
+            1 => Some(Level::Warn),
+            2 => Some(Level::Info),
+            3 => Some(Level::Debug),
+            _ => None,
+        }
+    }
+}
+
+/// A hypercall handler receives the four argument registers.
+pub type Handler = Box<dyn FnMut(&[u64; 4]) -> Action>;
+
+/// The table of hypercall handlers the run loop dispatches to.
+pub struct Hypercalls {
+    handlers: HashMap<u64, Handler>,
+}
+
+impl Default for Hypercalls {
+    /// Creates a table with the standard `EXIT`, `TIME` and `LOG` handlers.
+    fn default() -> Self {
+        let mut hypercalls = Hypercalls::new();
+        hypercalls.register(EXIT, |args| Action::Exit(args[0]));
+        hypercalls.register(TIME, |_| {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("system clock is before the UNIX epoch");
+            Action::Return(now.as_secs())
+        });
+        hypercalls.register(LOG, |args| match Level::from_arg(args[0]) {
+            Some(level) => {
+                println!(
+                    "          GUEST: [{:?}] code={:#x} values={:#x},{:#x}",
+                    level, args[1], args[2], args[3]
+                );
+                Action::Return(0)
+            }
+            None => Action::Return(ENOSYS),
+        });
+        hypercalls
+    }
+}
+
+impl Hypercalls {
+    /// Creates an empty table; every hypercall returns `ENOSYS`.
+    pub fn new() -> Self {
+        Hypercalls {
+            handlers: HashMap::new(),
+        }
+    }
+
+    /// Registers a handler for the hypercall number, replacing any previous one.
+    pub fn register<F>(&mut self, nr: u64, handler: F)
+    where
+        F: FnMut(&[u64; 4]) -> Action + 'static,
+    {
+        self.handlers.insert(nr, Box::new(handler));
+    }
+
+    /// Decodes the hypercall from the guest's registers and runs its handler.
+    /// For `Action::Return`, the return value is stored in `regs.rax`; the
+    /// caller is responsible for writing the registers back to the vCPU.
+    pub fn dispatch(&mut self, regs: &mut arch::Registers) -> Action {
+        let args = [regs.rbx, regs.rcx, regs.rsi, regs.rdi];
+        let action = match self.handlers.get_mut(&regs.rax) {
+            Some(handler) => handler(&args),
+            None => Action::Return(ENOSYS),
+        };
+
+        if let Action::Return(value) = action {
+            regs.rax = value;
+        }
+
+        action
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(hypercalls: &mut Hypercalls, nr: u64, args: [u64; 4]) -> (Action, u64) {
+        let mut regs = arch::Registers {
+            rax: nr,
+            rbx: args[0],
+            rcx: args[1],
+            rsi: args[2],
+            rdi: args[3],
+            rdx: PORT as u64,
+            ..Default::default()
+        };
+        let action = hypercalls.dispatch(&mut regs);
+        (action, regs.rax)
+    }
+
+    #[test]
+    fn exit_stops_with_the_guest_result() {
+        let mut hypercalls = Hypercalls::default();
+        // The guest is not resumed, so %rax keeps the hypercall number.
+        assert_eq!(
+            call(&mut hypercalls, EXIT, [42, 0, 0, 0]),
+            (Action::Exit(42), EXIT)
+        );
+    }
+
+    #[test]
+    fn time_returns_the_host_time() {
+        let mut hypercalls = Hypercalls::default();
+        let before = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let (action, rax) = call(&mut hypercalls, TIME, [0; 4]);
+        assert_eq!(action, Action::Return(rax));
+        assert!(rax >= before && rax <= before + 1);
+    }
+
+    #[test]
+    fn log_checks_the_level() {
+        let mut hypercalls = Hypercalls::default();
+        assert_eq!(
+            call(&mut hypercalls, LOG, [2, 0x10, 1, 2]),
+            (Action::Return(0), 0)
+        );
+        assert_eq!(
+            call(&mut hypercalls, LOG, [4, 0x10, 1, 2]),
+            (Action::Return(ENOSYS), ENOSYS)
+        );
+    }
+
+    #[test]
+    fn unknown_hypercall_returns_enosys() {
+        let mut hypercalls = Hypercalls::default();
+        assert_eq!(
+            call(&mut hypercalls, 3, [0; 4]),
+            (Action::Return(ENOSYS), ENOSYS)
+        );
+        assert_eq!(
+            call(&mut Hypercalls::new(), EXIT, [42, 0, 0, 0]),
+            (Action::Return(ENOSYS), ENOSYS)
+        );
+    }
+
+    #[test]
+    fn handlers_receive_the_argument_registers() {
+        let mut hypercalls = Hypercalls::new();
+        hypercalls.register(7, |args| Action::Return(args.iter().sum()));
+        assert_eq!(
+            call(&mut hypercalls, 7, [1, 20, 300, 4000]),
+            (Action::Return(4321), 4321)
+        );
+    }
+}
@@ -12,12 +12,38 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+mod hypercall;
+mod ownership;
+mod program;
+mod watchdog;
+
 use codicon::*;
 use ketuvim::{
     arch, sev::sev, util::map, Kvm, MemoryFlags, Reason, ReasonIo, VirtualCpu, VirtualMachine,
 };
+use program::{Operation, Program};
 use std::convert::TryFrom;
 use std::fs::File;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use watchdog::{Limits, Outcome, Stats, Watchdog};
+
+const USAGE: &str = "Usage: demo [--timeout SECONDS] [--max-exits COUNT] [--oca FILE]... \
+                     [add|sub|mul|div] NUMBER NUMBER
+       demo own OCA_FILE OCA_KEY_FILE";
+
+/// The platform's certificate chain is cached here between runs.
+const CHAIN_CACHE: &str = "/tmp/demo.chain";
+
+/// The parsed command line.
+struct Options {
+    limits: Limits,
+
+    /// The OCA certificates the client trusts. If empty, any OCA is accepted.
+    ocas: Vec<PathBuf>,
+
+    positional: Vec<String>,
+}
 
 fn fetch_chain(fw: &sev::firmware::Firmware) -> sev::certs::Chain {
     const CEK_SVC: &str = "https://kdsintf.amd.com/cek/id";
@@ -44,49 +70,137 @@ fn fetch_chain(fw: &sev::firmware::Firmware) -> sev::certs::Chain {
     }
 }
 
-fn main() {
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() != 3 {
-        eprintln!("Usage: demo NUMBER NUMBER");
-        std::process::exit(1);
+/// Splits the command line into the options and the positional arguments.
+fn parse_args() -> Options {
+    let mut limits = Limits::default();
+    let mut ocas = Vec::new();
+    let mut positional = Vec::new();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            _ if limits.parse_option(&arg, &mut args) => (),
+            "--oca" => ocas.push(PathBuf::from(
+                args.next().expect("--oca needs a certificate file!"),
+            )),
+            _ => positional.push(arg),
+        }
     }
 
-    let a = args[1].trim().parse::<u8>().expect("Must be a number!");
-    if a > 4 {
-        panic!("Number must be between 0 and 4, inclusive!");
+    Options {
+        limits,
+        ocas,
+        positional,
     }
+}
+
+/// Takes ownership of the platform with the organisation's OCA. The OCA is
+/// generated and saved on first use, and reused from then on. If only one of
+/// its files exists, nothing is done: a new OCA would replace the one tenants
+/// have pinned.
+fn own(cert_path: &str, key_path: &str) {
+    let (cert_path, key_path) = (Path::new(cert_path), Path::new(key_path));
+    let oca = match (key_path.exists(), cert_path.exists()) {
+        (true, true) => ownership::Oca::load(key_path, cert_path),
+        (true, false) => panic!(
+            "The OCA key {} exists, but not its certificate {}",
+            key_path.display(),
+            cert_path.display()
+        ),
+        (false, true) => panic!(
+            "The OCA certificate {} exists, but not its key {}",
+            cert_path.display(),
+            key_path.display()
+        ),
+        (false, false) => {
+            let oca = ownership::Oca::generate();
+            if let Ok(oca) = &oca {
+                oca.save(key_path, cert_path)
+                    .unwrap_or_else(|e| panic!("Unable to save the OCA: {}", e));
+                println!("         SERVER: Generated OCA {}", key_path.display());
+            }
+            oca
+        }
+    }
+    .unwrap_or_else(|e| panic!("Unable to load the OCA: {}", e));
+
+    let mut fw = sev::firmware::Firmware::open()
+        .expect("unable to open /dev/sev; run sev-detect for a readiness report");
+    ownership::take(&mut fw, &oca).unwrap_or_else(|e| panic!("Unable to take ownership: {}", e));
 
-    let b = args[2].trim().parse::<u8>().expect("Must be a number!");
-    if b > 4 {
-        panic!("Number must be between 0 and 4, inclusive!");
+    // The PEK and PDH have changed, so the cached chain is stale.
+    let _ = std::fs::remove_file(CHAIN_CACHE);
+    println!(
+        "         SERVER: Platform owned by OCA {}",
+        cert_path.display()
+    );
+}
+
+fn main() {
+    let Options {
+        limits,
+        ocas,
+        positional: args,
+    } = parse_args();
+
+    if args.len() == 3 && args[0] == "own" {
+        own(&args[1], &args[2]);
+        return;
     }
 
-    let code = [
-        0xba, 0xf8, 0x03, // mov $0x3f8, %dx
-        0xb0, a, // mov a, %al
-        0xb3, b, // mov b, %bl
-        0x00, 0xd8, // add %bl, %al
-        0x04, b'0', // add $'0', %al
-        0xee, // out %al, (%dx)
-        0xb0, b'\n', // mov $'\n', %al
-        0xee,  // out %al, (%dx)
-        0xf4,  // hlt
-    ];
+    let trusted = ocas
+        .iter()
+        .map(|path| ownership::load(path))
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap_or_else(|e| panic!("{}", e));
+
+    let (op, operands) = match args.len() {
+        2 => (Operation::Add, &args[..]),
+        3 => (
+            args[0].parse().unwrap_or_else(|e| panic!("{}", e)),
+            &args[1..],
+        ),
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(1);
+        }
+    };
+
+    let a = operands[0]
+        .trim()
+        .parse::<u32>()
+        .expect("Must be a 32-bit unsigned number!");
+    let b = operands[1]
+        .trim()
+        .parse::<u32>()
+        .expect("Must be a 32-bit unsigned number!");
+
+    let program = Program::new(op, a, b).unwrap_or_else(|e| panic!("Invalid program: {}", e));
+    let code = program.code();
+    println!("CLIENT         : Program {}", program);
 
     // Server delivers chain and build to client...
-    let fw = sev::firmware::Firmware::open().unwrap();
+    let fw = sev::firmware::Firmware::open()
+        .expect("unable to open /dev/sev; run sev-detect for a readiness report");
     let build = fw.platform_status().unwrap().build;
-    let chain = if let Ok(mut file) = File::open("/tmp/demo.chain") {
+    let chain = if let Ok(mut file) = File::open(CHAIN_CACHE) {
         sev::certs::Chain::decode(&mut file, ()).unwrap()
     } else {
         println!("         SERVER: Fetch Certificate Chain");
         let chain = fetch_chain(&fw);
-        let mut file = File::create("/tmp/demo.chain").unwrap();
+        let mut file = File::create(CHAIN_CACHE).unwrap();
         chain.encode(&mut file, ()).unwrap();
         chain
     };
     println!("CLIENT < SERVER: Certificate Chain");
 
+    // Client checks that the platform is owned by a trusted OCA.
+    if !trusted.is_empty() {
+        ownership::verify(&chain.sev, &trusted)
+            .unwrap_or_else(|e| panic!("Platform ownership rejected: {}", e));
+        println!("CLIENT         : Platform Owner OK");
+    }
+
     // Client creates session and starts the launch.
     let policy = sev::launch::Policy::default();
     let session = sev::session::Session::try_from(policy).unwrap();
@@ -95,7 +209,7 @@ fn main() {
     println!("CLIENT > SERVER: Policy, Session Keys");
 
     // Server spins up the VM.
-    let kvm = Kvm::open().unwrap();
+    let kvm = Kvm::open().expect("unable to open /dev/kvm; run sev-detect for a readiness report");
     let mut vm = VirtualMachine::new(&kvm).unwrap();
     let mem = map::Map::<()>::build(map::Access::Shared)
         .protection(map::Protection::READ | map::Protection::WRITE)
@@ -104,7 +218,7 @@ fn main() {
         .done()
         .unwrap();
     let addr = &*mem as *const () as u64;
-    vm.add_region(0, MemoryFlags::default(), 0x1000, mem)
+    vm.add_region(0, MemoryFlags::default(), program::ORIGIN, mem)
         .unwrap();
 
     // Server takes a measurement and sends it to the client.
@@ -143,19 +257,39 @@ fn main() {
 
     // Setup registers.
     cpu.set_registers(arch::Registers {
-        rip: 0x1000,
+        rip: program::ORIGIN,
         rflags: 0x2,
         ..Default::default()
     })
     .unwrap();
 
-    loop {
-        match cpu.run().unwrap() {
-            Reason::Halt => break,
+    let mut hypercalls = hypercall::Hypercalls::default();
+    let mut output = Vec::new();
+    let mut stats = Stats::default();
+    let watchdog = Watchdog::arm(limits.timeout);
+
+    let outcome = loop {
+        if stats.exits() >= limits.max_exits {
+            break Outcome::TooManyExits;
+        }
+        if watchdog.expired() {
+            break Outcome::TimedOut;
+        }
+
+        let reason = match cpu.run() {
+            Ok(reason) => reason,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => panic!("Unable to run vCPU: {}", e),
+        };
+        stats.record(&reason);
+
+        match reason {
+            Reason::Halt => break Outcome::Halted,
 
             Reason::Io(io) => match io {
                 ReasonIo::Out { port, data } => match port {
-                    0x03f8 => {
+                    program::SERIAL_PORT => {
+                        output.extend_from_slice(data);
                         for b in data {
                             unsafe {
                                 libc::putchar(*b as i32);
@@ -163,6 +297,14 @@ fn main() {
                         }
                     }
 
+                    hypercall::PORT => {
+                        let mut regs = cpu.registers().unwrap();
+                        match hypercalls.dispatch(&mut regs) {
+                            hypercall::Action::Return(_) => cpu.set_registers(regs).unwrap(),
+                            hypercall::Action::Exit(value) => break Outcome::Exited(value),
+                        }
+                    }
+
                     _ => panic!("Unexpected IO port!"),
                 },
 
@@ -170,5 +312,36 @@ fn main() {
             },
             other => panic!("Unexpected reason: {:?}", other),
         }
+    };
+    drop(watchdog);
+    stats.finish();
+
+    match outcome {
+        Outcome::Halted => println!("         SERVER: Guest Halted"),
+        Outcome::Exited(value) => println!("         SERVER: Guest Exited: {}", value),
+        Outcome::TimedOut => println!(
+            "         SERVER: Guest Killed: ran longer than {:?}",
+            limits.timeout
+        ),
+        Outcome::TooManyExits => println!(
+            "         SERVER: Guest Killed: reached the exit limit of {}",
+            limits.max_exits
+        ),
+    }
+    print!("{}", stats);
+
+    // Server validates the guest's output against the expected result.
+    let expected = program.expected();
+    let printed = String::from_utf8_lossy(&output);
+    let ok =
+        outcome == Outcome::Exited(u64::from(expected)) && printed == program.expected_output();
+    if ok {
+        println!("         SERVER: Result OK: {} = {}", program, expected);
+    } else {
+        println!(
+            "         SERVER: Result MISMATCH: expected {} = {}, guest printed {:?}",
+            program, expected, printed
+        );
+        std::process::exit(1);
     }
 }
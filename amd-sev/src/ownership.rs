@@ -0,0 +1,364 @@
+// Copyright 2019 Red Hat
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Platform ownership.
+//!
+//! Out of the box, an SEV platform is self-owned: the firmware signs its own
+//! PEK with a self-generated OCA. Taking ownership replaces that OCA with an
+//! organisational one: we sign the PEK certificate signing request with the
+//! organisation's OCA key and import the signed PEK back into the firmware.
+//!
+//! The OCA is generated once and kept, key and certificate, so that the same
+//! organisation can own any number of platforms, and take ownership again
+//! whenever a platform's PEK is regenerated. The key is an ECDSA P-384 key,
+//! kept in PEM; the certificate is kept in the firmware's binary format.
+//!
+//! Guest owners can then pin the OCA certificate and refuse to launch guests
+//! on platforms whose PEK was not signed by it. The PEK remains signed by the
+//! CEK as well, so the AMD chain is still verified on top of this.
+
+use codicon::*;
+use ketuvim::sev::sev::certs::{sev, Verifiable};
+use ketuvim::sev::sev::firmware::Firmware;
+use openssl::bn::{BigNum, BigNumContext, BigNumRef};
+use openssl::ec::{EcGroup, EcKey};
+use openssl::ecdsa::EcdsaSig;
+use openssl::nid::Nid;
+use openssl::pkey::Private;
+use openssl::sha::sha256;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+
+// The layout of an SEV certificate, as defined in the SEV API specification
+// (appendix C.1): the signed body, which holds the public key, followed by
+// two signature slots.
+const CERT_LEN: usize = 0x824;
+const BODY_LEN: usize = 0x414;
+const USAGE: usize = 0x8;
+const ALGO: usize = 0xc;
+const PUBKEY: usize = 0x10;
+const SIG1: usize = 0x414;
+const SIG2: usize = 0x61c;
+
+// Within a public key: the curve, then both coordinates. Within a signature
+// slot: the usage and algorithm of the signing key, then R and S. Numbers are
+// little-endian, and zero-padded to 72 bytes.
+const CURVE: usize = 0x0;
+const QX: usize = 0x4;
+const QY: usize = 0x4c;
+const SIG_R: usize = 0x8;
+const SIG_S: usize = 0x50;
+const NUMBER_LEN: usize = 0x48;
+
+const VERSION: u32 = 1;
+const USAGE_OCA: u32 = 0x1001;
+const USAGE_INVALID: u32 = 0x1000;
+const ALGO_ECDSA_SHA256: u32 = 0x13;
+const CURVE_P384: u32 = 2;
+
+/// An organisation's OCA: its signing key and its self-signed certificate.
+pub struct Oca {
+    key: EcKey<Private>,
+    cert: Vec<u8>,
+}
+
+impl Oca {
+    /// Generates a new OCA key pair, and self-signs its certificate.
+    pub fn generate() -> Result<Self, String> {
+        let group = EcGroup::from_curve_name(Nid::SECP384R1).map_err(ssl)?;
+        let key = EcKey::generate(&group).map_err(ssl)?;
+
+        let mut cert = vec![0u8; CERT_LEN];
+        put_u32(&mut cert, 0, VERSION);
+        put_u32(&mut cert, USAGE, USAGE_OCA);
+        put_u32(&mut cert, ALGO, ALGO_ECDSA_SHA256);
+        put_u32(&mut cert, PUBKEY + CURVE, CURVE_P384);
+        let (x, y) = coordinates(&key)?;
+        put_number(&mut cert, PUBKEY + QX, &x)?;
+        put_number(&mut cert, PUBKEY + QY, &y)?;
+        put_u32(&mut cert, SIG2, USAGE_INVALID);
+
+        let oca = Oca { key, cert };
+        let mut cert = oca.cert.clone();
+        oca.sign(&mut cert)?;
+        Ok(Oca { cert, ..oca })
+    }
+
+    /// Loads an OCA saved with `save`, checking that the certificate is an
+    /// OCA certificate for the key.
+    pub fn load(key_path: &Path, cert_path: &Path) -> Result<Self, String> {
+        let pem = fs::read(key_path)
+            .map_err(|e| format!("unable to read {}: {}", key_path.display(), e))?;
+        let key = EcKey::private_key_from_pem(&pem)
+            .map_err(|e| format!("invalid OCA key {}: {}", key_path.display(), e))?;
+        let cert = fs::read(cert_path)
+            .map_err(|e| format!("unable to read {}: {}", cert_path.display(), e))?;
+
+        let invalid =
+            |why: &str| format!("invalid OCA certificate {}: {}", cert_path.display(), why);
+        if cert.len() != CERT_LEN || get_u32(&cert, USAGE) != USAGE_OCA {
+            return Err(invalid("not an OCA certificate"));
+        }
+        let (x, y) = coordinates(&key)?;
+        if get_u32(&cert, PUBKEY + CURVE) != CURVE_P384
+            || get_number(&cert, PUBKEY + QX) != little_endian(&x)?
+            || get_number(&cert, PUBKEY + QY) != little_endian(&y)?
+        {
+            return Err(invalid("it does not match the key"));
+        }
+
+        Ok(Oca { key, cert })
+    }
+
+    /// Saves the OCA. The key is only readable by its owner, and an existing
+    /// key or certificate is never overwritten.
+    pub fn save(&self, key_path: &Path, cert_path: &Path) -> Result<(), String> {
+        let pem = self.key.private_key_to_pem().map_err(ssl)?;
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(key_path)
+            .and_then(|mut file| file.write_all(&pem))
+            .map_err(|e| format!("unable to write {}: {}", key_path.display(), e))?;
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(cert_path)
+            .and_then(|mut file| file.write_all(&self.cert))
+            .map_err(|e| {
+                // The key is useless without its certificate.
+                let _ = fs::remove_file(key_path);
+                format!("unable to write {}: {}", cert_path.display(), e)
+            })
+    }
+
+    /// The OCA certificate.
+    pub fn certificate(&self) -> Result<sev::Certificate, String> {
+        sev::Certificate::decode(&mut &self.cert[..], ())
+            .map_err(|e| format!("invalid OCA certificate: {}", e))
+    }
+
+    /// Signs an encoded certificate with the OCA key, into its first
+    /// signature slot.
+    fn sign(&self, cert: &mut [u8]) -> Result<(), String> {
+        if cert.len() != CERT_LEN {
+            return Err(format!("certificate of {} bytes", cert.len()));
+        }
+
+        let digest = sha256(&cert[..BODY_LEN]);
+        let sig = EcdsaSig::sign(&digest, &self.key).map_err(ssl)?;
+        put_u32(cert, SIG1, USAGE_OCA);
+        put_u32(cert, SIG1 + 4, ALGO_ECDSA_SHA256);
+        put_number(cert, SIG1 + SIG_R, sig.r())?;
+        put_number(cert, SIG1 + SIG_S, sig.s())
+    }
+}
+
+/// Signs the platform's PEK with the OCA and imports the signed PEK.
+pub fn take(fw: &mut Firmware, oca: &Oca) -> Result<(), String> {
+    let pek = fw
+        .pek_csr()
+        .map_err(|e| format!("unable to fetch PEK CSR: {:?}", e))?;
+
+    let mut buf = Vec::new();
+    pek.encode(&mut buf, ())
+        .map_err(|e| format!("unable to encode PEK CSR: {}", e))?;
+    oca.sign(&mut buf)
+        .map_err(|e| format!("unable to sign PEK: {}", e))?;
+    let pek = sev::Certificate::decode(&mut &buf[..], ())
+        .map_err(|e| format!("unable to decode signed PEK: {}", e))?;
+
+    fw.pek_cert_import(&pek, &oca.certificate()?)
+        .map_err(|e| format!("unable to import PEK: {:?}", e))
+}
+
+fn coordinates(key: &EcKey<Private>) -> Result<(BigNum, BigNum), String> {
+    let mut ctx = BigNumContext::new().map_err(ssl)?;
+    let mut x = BigNum::new().map_err(ssl)?;
+    let mut y = BigNum::new().map_err(ssl)?;
+    key.public_key()
+        .affine_coordinates_gfp(key.group(), &mut x, &mut y, &mut ctx)
+        .map_err(ssl)?;
+    Ok((x, y))
+}
+
+fn little_endian(number: &BigNumRef) -> Result<Vec<u8>, String> {
+    let mut bytes = number.to_vec();
+    if bytes.len() > NUMBER_LEN {
+        return Err(format!("number of {} bytes", bytes.len()));
+    }
+    bytes.reverse();
+    bytes.resize(NUMBER_LEN, 0);
+    Ok(bytes)
+}
+
+fn put_number(cert: &mut [u8], offset: usize, number: &BigNumRef) -> Result<(), String> {
+    cert[offset..offset + NUMBER_LEN].copy_from_slice(&little_endian(number)?);
+    Ok(())
+}
+
+fn get_number(cert: &[u8], offset: usize) -> &[u8] {
+    &cert[offset..offset + NUMBER_LEN]
+}
+
+fn put_u32(cert: &mut [u8], offset: usize, value: u32) {
+    cert[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn get_u32(cert: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0u8; 4];
+    bytes.copy_from_slice(&cert[offset..offset + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+fn ssl(e: openssl::error::ErrorStack) -> String {
+    format!("OpenSSL error: {}", e)
+}
+
+/// Loads a pinned OCA certificate.
+pub fn load(path: &Path) -> Result<sev::Certificate, String> {
+    let mut file =
+        File::open(path).map_err(|e| format!("unable to open {}: {}", path.display(), e))?;
+    sev::Certificate::decode(&mut file, ())
+        .map_err(|e| format!("invalid OCA certificate {}: {}", path.display(), e))
+}
+
+/// Verifies that the platform's certificate chain is rooted in one of the
+/// trusted OCAs: the chain's OCA must be trusted and self-signed, it must
+/// have signed the PEK and the PEK must have signed the PDH.
+pub fn verify(chain: &sev::Chain, trusted: &[sev::Certificate]) -> Result<(), String> {
+    let oca = encode(&chain.oca)?;
+    let mut pinned = false;
+    for cert in trusted {
+        pinned |= encode(cert)? == oca;
+    }
+    if !pinned {
+        return Err("the platform's OCA is not trusted".into());
+    }
+
+    (&chain.oca, &chain.oca)
+        .verify()
+        .map_err(|e| format!("OCA is not self-signed: {}", e))?;
+    (&chain.oca, &chain.pek)
+        .verify()
+        .map_err(|e| format!("PEK is not signed by the OCA: {}", e))?;
+    (&chain.pek, &chain.pdh)
+        .verify()
+        .map_err(|e| format!("PDH is not signed by the PEK: {}", e))?;
+
+    Ok(())
+}
+
+fn encode(cert: &sev::Certificate) -> Result<Vec<u8>, String> {
+    let mut buf = Vec::new();
+    cert.encode(&mut buf, ())
+        .map_err(|e| format!("unable to encode certificate: {}", e))?;
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// Checks the signature in the certificate's first slot with the key.
+    fn signed_by(cert: &[u8], oca: &Oca) -> bool {
+        let number = |offset: usize| {
+            let mut bytes = get_number(cert, offset).to_vec();
+            bytes.reverse();
+            BigNum::from_slice(&bytes).unwrap()
+        };
+        let sig =
+            EcdsaSig::from_private_components(number(SIG1 + SIG_R), number(SIG1 + SIG_S)).unwrap();
+        get_u32(cert, SIG1) == USAGE_OCA
+            && get_u32(cert, SIG1 + 4) == ALGO_ECDSA_SHA256
+            && sig.verify(&sha256(&cert[..BODY_LEN]), &oca.key).unwrap()
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("oca-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn generated_oca_is_self_signed() {
+        let oca = Oca::generate().unwrap();
+        assert_eq!(oca.cert.len(), CERT_LEN);
+        assert_eq!(get_u32(&oca.cert, USAGE), USAGE_OCA);
+        assert_eq!(get_u32(&oca.cert, SIG2), USAGE_INVALID);
+        assert!(signed_by(&oca.cert, &oca));
+        assert!(!signed_by(&oca.cert, &Oca::generate().unwrap()));
+    }
+
+    #[test]
+    fn pek_is_signed_by_the_oca() {
+        let oca = Oca::generate().unwrap();
+        let mut pek = vec![0x5a; CERT_LEN];
+        oca.sign(&mut pek).unwrap();
+        assert!(signed_by(&pek, &oca));
+
+        pek[PUBKEY] ^= 1;
+        assert!(!signed_by(&pek, &oca));
+    }
+
+    #[test]
+    fn saved_oca_is_loaded_again() {
+        let dir = temp_dir("saved");
+        let (key, cert) = (dir.join("oca.key"), dir.join("oca.cert"));
+        let oca = Oca::generate().unwrap();
+        oca.save(&key, &cert).unwrap();
+
+        let loaded = Oca::load(&key, &cert).unwrap();
+        assert_eq!(loaded.cert, oca.cert);
+        let mut pek = vec![0; CERT_LEN];
+        loaded.sign(&mut pek).unwrap();
+        assert!(signed_by(&pek, &oca));
+
+        // The key is private, and never overwritten.
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(
+            fs::metadata(&key).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+        assert!(Oca::generate().unwrap().save(&key, &cert).is_err());
+        assert_eq!(Oca::load(&key, &cert).unwrap().cert, oca.cert);
+
+        // Nor is a certificate whose key is missing.
+        fs::remove_file(&key).unwrap();
+        assert!(Oca::generate().unwrap().save(&key, &cert).is_err());
+        assert_eq!(fs::read(&cert).unwrap(), oca.cert);
+        assert!(!key.exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn certificate_of_another_oca_is_rejected() {
+        let dir = temp_dir("mismatch");
+        let (key, cert) = (dir.join("oca.key"), dir.join("oca.cert"));
+        Oca::generate().unwrap().save(&key, &cert).unwrap();
+        fs::write(&cert, &Oca::generate().unwrap().cert).unwrap();
+        assert!(Oca::load(&key, &cert).is_err());
+
+        fs::write(&cert, b"not a certificate").unwrap();
+        assert!(Oca::load(&key, &cert).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
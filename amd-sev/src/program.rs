@@ -0,0 +1,404 @@
+// Copyright 2019 Red Hat
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The guest program generator.
+//!
+//! The guest runs in real mode at `ORIGIN`, computes a single arithmetic
+//! operation on two 32-bit operands, prints the result in decimal on the
+//! serial port and then hands the result to the host with the `EXIT`
+//! hypercall. All arithmetic wraps modulo 2^32, just like the CPU does.
+
+use crate::hypercall;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+/// The guest physical address the program is loaded at.
+pub const ORIGIN: u64 = 0x1000;
+
+/// The top of the guest's stack: the end of the page the program lives in.
+const STACK_TOP: u16 = 0x2000;
+
+/// The serial port the guest prints its result on.
+pub const SERIAL_PORT: u16 = 0x03f8;
+
+/// An arithmetic operation the guest can perform.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Operation {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl FromStr for Operation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "add" | "+" => Ok(Operation::Add),
+            "sub" | "-" => Ok(Operation::Sub),
+            "mul" | "*" => Ok(Operation::Mul),
+            "div" | "/" => Ok(Operation::Div),
+            other => Err(format!(
+                "unknown operation {:?}; expected add, sub, mul or div",
+                other
+            )),
+        }
+    }
+}
+
+impl Display for Operation {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let symbol = match self {
+            Operation::Add => "+",
+            Operation::Sub => "-",
+            Operation::Mul => "*",
+            Operation::Div => "/",
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
+impl Operation {
+    /// The machine code that computes `%eax op %ebx` into `%eax`.
+    fn code(self) -> &'static [u8] {
+        match self {
+            Operation::Add => &[0x66, 0x01, 0xd8], // add %ebx, %eax
+            Operation::Sub => &[0x66, 0x29, 0xd8], // sub %ebx, %eax
+            Operation::Mul => &[0x66, 0xf7, 0xe3], // mul %ebx
+            Operation::Div => &[
+                0x66, 0x31, 0xd2, // xor %edx, %edx
+                0x66, 0xf7, 0xf3, // div %ebx
+            ],
+        }
+    }
+}
+
+/// A guest program computing `a op b`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Program {
+    op: Operation,
+    a: u32,
+    b: u32,
+}
+
+impl Display for Program {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{} {} {}", self.a, self.op, self.b)
+    }
+}
+
+impl Program {
+    /// Creates a program, rejecting division by zero, which would fault the guest.
+    pub fn new(op: Operation, a: u32, b: u32) -> Result<Self, String> {
+        if op == Operation::Div && b == 0 {
+            return Err("division by zero".into());
+        }
+
+        Ok(Program { op, a, b })
+    }
+
+    /// The result the guest is expected to produce.
+    pub fn expected(&self) -> u32 {
+        match self.op {
+            Operation::Add => self.a.wrapping_add(self.b),
+            Operation::Sub => self.a.wrapping_sub(self.b),
+            Operation::Mul => self.a.wrapping_mul(self.b),
+            Operation::Div => self.a / self.b,
+        }
+    }
+
+    /// The text the guest is expected to print on the serial port.
+    pub fn expected_output(&self) -> String {
+        format!("{}\n", self.expected())
+    }
+
+    /// Assembles the guest program.
+    pub fn code(&self) -> Vec<u8> {
+        let mut code = Vec::new();
+
+        // mov $a, %eax
+        code.extend_from_slice(&[0x66, 0xb8]);
+        code.extend_from_slice(&self.a.to_le_bytes());
+
+        // mov $b, %ebx
+        code.extend_from_slice(&[0x66, 0xbb]);
+        code.extend_from_slice(&self.b.to_le_bytes());
+
+        code.extend_from_slice(self.op.code());
+
+        let stack = STACK_TOP.to_le_bytes();
+        let serial = SERIAL_PORT.to_le_bytes();
+        let hypercall = hypercall::PORT.to_le_bytes();
+
+        #[rustfmt::skip]
+        code.extend_from_slice(&[
+            0x66, 0x89, 0xc6, // mov %eax, %esi
+            0xbc, stack[0], stack[1], // mov $STACK_TOP, %sp
+
+            // Push the decimal digits, least significant first.
+            0x66, 0xb9, 0x0a, 0x00, 0x00, 0x00, // mov $10, %ecx
+            0x31, 0xff, // xor %di, %di
+            0x66, 0x31, 0xd2, // 1: xor %edx, %edx
+            0x66, 0xf7, 0xf1, // div %ecx
+            0x52, // push %dx
+            0x47, // inc %di
+            0x66, 0x85, 0xc0, // test %eax, %eax
+            0x75, 0xf3, // jnz 1b
+
+            // Pop and print them, most significant first.
+            0xba, serial[0], serial[1], // mov $SERIAL_PORT, %dx
+            0x58, // 2: pop %ax
+            0x04, b'0', // add $'0', %al
+            0xee, // out %al, (%dx)
+            0x4f, // dec %di
+            0x75, 0xf9, // jnz 2b
+            0xb0, b'\n', // mov $'\n', %al
+            0xee, // out %al, (%dx)
+
+            // Return the result to the host.
+            0x66, 0x89, 0xf3, // mov %esi, %ebx
+            0x66, 0x31, 0xc0, // xor %eax, %eax (hypercall::EXIT)
+            0xba, hypercall[0], hypercall[1], // mov $hypercall::PORT, %dx
+            0xee, // out %al, (%dx)
+            0xf4, // hlt
+        ]);
+
+        // The launch secret is encrypted in 16-byte blocks.
+        while code.len() % 16 != 0 {
+            code.push(0xf4); // hlt
+        }
+
+        code
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hypercall::{Action, Hypercalls};
+    use ketuvim::arch;
+
+    /// What the guest did: its output on the serial port, the result it passed
+    /// to the `EXIT` hypercall, and where in the code it did so.
+    struct Run {
+        output: String,
+        result: u64,
+        exit_at: usize,
+    }
+
+    /// Interprets the real-mode instructions `Program::code` emits, with the
+    /// stack in the program's page, until the guest exits.
+    fn run(code: &[u8]) -> Run {
+        let mut memory = vec![0u8; STACK_TOP as usize];
+        let [mut eax, mut ebx, mut ecx, mut edx, mut esi, mut edi] = [0u32; 6];
+        let mut sp = 0u16;
+        let mut zf = false;
+        let mut output = Vec::new();
+        let mut hypercalls = Hypercalls::default();
+
+        let low = |reg: &mut u32, value: u16| *reg = (*reg & 0xffff_0000) | u32::from(value);
+        let mut ip = 0;
+        for _ in 0..10_000 {
+            let imm16 = |at: usize| u16::from_le_bytes([code[at], code[at + 1]]);
+            let imm32 = |at: usize| {
+                u32::from_le_bytes([code[at], code[at + 1], code[at + 2], code[at + 3]])
+            };
+            let wide = code[ip] == 0x66;
+            let op = if wide { ip + 1 } else { ip };
+            ip = match (wide, code[op], code.get(op + 1).copied().unwrap_or(0)) {
+                (true, 0xb8, _) => {
+                    eax = imm32(op + 1);
+                    op + 5
+                }
+                (true, 0xbb, _) => {
+                    ebx = imm32(op + 1);
+                    op + 5
+                }
+                (true, 0xb9, _) => {
+                    ecx = imm32(op + 1);
+                    op + 5
+                }
+                (true, 0x01, 0xd8) => {
+                    eax = eax.wrapping_add(ebx);
+                    op + 2
+                }
+                (true, 0x29, 0xd8) => {
+                    eax = eax.wrapping_sub(ebx);
+                    op + 2
+                }
+                (true, 0xf7, 0xe3) => {
+                    let product = u64::from(eax) * u64::from(ebx);
+                    eax = product as u32;
+                    edx = (product >> 32) as u32;
+                    op + 2
+                }
+                (true, 0xf7, modrm @ 0xf1) | (true, 0xf7, modrm @ 0xf3) => {
+                    let divisor = u64::from(if modrm == 0xf1 { ecx } else { ebx });
+                    let dividend = (u64::from(edx) << 32) | u64::from(eax);
+                    eax = (dividend / divisor) as u32;
+                    edx = (dividend % divisor) as u32;
+                    op + 2
+                }
+                (true, 0x31, 0xd2) => {
+                    edx = 0;
+                    zf = true;
+                    op + 2
+                }
+                (true, 0x31, 0xc0) => {
+                    eax = 0;
+                    zf = true;
+                    op + 2
+                }
+                (true, 0x85, 0xc0) => {
+                    zf = eax == 0;
+                    op + 2
+                }
+                (true, 0x89, 0xc6) => {
+                    esi = eax;
+                    op + 2
+                }
+                (true, 0x89, 0xf3) => {
+                    ebx = esi;
+                    op + 2
+                }
+                (false, 0xbc, _) => {
+                    sp = imm16(op + 1);
+                    op + 3
+                }
+                (false, 0xba, _) => {
+                    low(&mut edx, imm16(op + 1));
+                    op + 3
+                }
+                (false, 0x31, 0xff) => {
+                    low(&mut edi, 0);
+                    zf = true;
+                    op + 2
+                }
+                (false, 0x47, _) | (false, 0x4f, _) => {
+                    let di = if code[op] == 0x47 {
+                        edi as u16 + 1
+                    } else {
+                        edi as u16 - 1
+                    };
+                    low(&mut edi, di);
+                    zf = di == 0;
+                    op + 1
+                }
+                (false, 0x52, _) => {
+                    sp -= 2;
+                    memory[sp as usize..][..2].copy_from_slice(&(edx as u16).to_le_bytes());
+                    op + 1
+                }
+                (false, 0x58, _) => {
+                    low(
+                        &mut eax,
+                        u16::from_le_bytes([memory[sp as usize], memory[sp as usize + 1]]),
+                    );
+                    sp += 2;
+                    op + 1
+                }
+                (false, 0x04, imm) => {
+                    let al = (eax as u8).wrapping_add(imm);
+                    eax = (eax & !0xff) | u32::from(al);
+                    zf = al == 0;
+                    op + 2
+                }
+                (false, 0xb0, imm) => {
+                    eax = (eax & !0xff) | u32::from(imm);
+                    op + 2
+                }
+                (false, 0x75, rel) => {
+                    let next = op + 2;
+                    if zf {
+                        next
+                    } else {
+                        (next as isize + isize::from(rel as i8)) as usize
+                    }
+                }
+                (false, 0xee, _) => match edx as u16 {
+                    SERIAL_PORT => {
+                        output.push(eax as u8);
+                        op + 1
+                    }
+                    hypercall::PORT => {
+                        let mut regs = arch::Registers {
+                            rax: eax.into(),
+                            rbx: ebx.into(),
+                            rcx: ecx.into(),
+                            rdx: edx.into(),
+                            rsi: esi.into(),
+                            rdi: edi.into(),
+                            ..Default::default()
+                        };
+                        match hypercalls.dispatch(&mut regs) {
+                            Action::Exit(result) => {
+                                return Run {
+                                    output: String::from_utf8(output).unwrap(),
+                                    result,
+                                    exit_at: op + 1,
+                                }
+                            }
+                            Action::Return(value) => {
+                                eax = value as u32;
+                                op + 1
+                            }
+                        }
+                    }
+                    port => panic!("write to unexpected port {:#x}", port),
+                },
+                (_, 0xf4, _) => panic!("the guest halted without exiting"),
+                _ => panic!("unexpected instruction at {:#x}: {:x?}", ip, &code[ip..]),
+            };
+        }
+        panic!("the guest did not exit");
+    }
+
+    #[test]
+    fn code_computes_the_expected_result() {
+        let programs = [
+            (Operation::Add, 0, 0),
+            (Operation::Add, 1234, 4321),
+            (Operation::Add, u32::MAX, 2),
+            (Operation::Sub, 3, 5),
+            (Operation::Mul, 65537, 65539),
+            (Operation::Div, 100, 7),
+            (Operation::Div, u32::MAX, 1),
+        ];
+
+        for &(op, a, b) in programs.iter() {
+            let program = Program::new(op, a, b).unwrap();
+            let code = program.code();
+            let run = run(&code);
+
+            assert_eq!(run.result, u64::from(program.expected()), "{}", program);
+            assert_eq!(run.output, program.expected_output(), "{}", program);
+
+            // The guest exits right before the final hlt, and the code is
+            // padded with hlt to the next 16-byte block, and no further.
+            assert_eq!(code.len() % 16, 0, "{}", program);
+            assert!(code.len() - run.exit_at <= 16, "{}", program);
+            assert!(
+                code[run.exit_at..].iter().all(|&b| b == 0xf4),
+                "{}",
+                program
+            );
+        }
+    }
+
+    #[test]
+    fn division_by_zero_is_rejected() {
+        assert!(Program::new(Operation::Div, 1, 0).is_err());
+    }
+}
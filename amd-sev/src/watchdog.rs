@@ -0,0 +1,389 @@
+// Copyright 2019 Red Hat
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Bounded guest execution.
+//!
+//! The `Watchdog` kicks the vCPU thread out of `KVM_RUN` once a wall-clock
+//! deadline passes, and `Stats` accounts for every exit so the run loop can
+//! also enforce a maximum exit count.
+
+use ketuvim::{Reason, ReasonIo};
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// The signal used to interrupt `KVM_RUN`.
+const KICK_SIGNAL: libc::c_int = libc::SIGUSR1;
+
+/// How often the watchdog re-sends the signal until the run loop notices.
+/// A signal that arrives just before the vCPU enters `KVM_RUN` is lost, so a
+/// single kick is not enough.
+const KICK_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The limits placed on a guest's execution.
+#[derive(Copy, Clone, Debug)]
+pub struct Limits {
+    /// The maximum wall-clock time the guest may run for.
+    pub timeout: Duration,
+
+    /// The maximum number of exits to the host the guest may cause.
+    pub max_exits: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            timeout: Duration::from_secs(10),
+            max_exits: 1_000_000,
+        }
+    }
+}
+
+impl Limits {
+    /// Applies `--timeout SECONDS` or `--max-exits COUNT`, taking the value
+    /// from `args`. Returns false, consuming nothing, for any other option.
+    pub fn parse_option(&mut self, option: &str, args: &mut impl Iterator<Item = String>) -> bool {
+        match option {
+            "--timeout" => {
+                let secs = args.next().and_then(|v| v.trim().parse::<u64>().ok());
+                self.timeout =
+                    Duration::from_secs(secs.expect("--timeout needs a number of seconds!"));
+            }
+            "--max-exits" => {
+                let count = args.next().and_then(|v| v.trim().parse::<u64>().ok());
+                self.max_exits = count.expect("--max-exits needs a number!");
+            }
+            _ => return false,
+        }
+        true
+    }
+}
+
+extern "C" fn kick(_: libc::c_int) {
+    // The handler only exists so that the signal interrupts the vCPU's
+    // KVM_RUN ioctl with EINTR instead of terminating the process.
+}
+
+/// Interrupts the thread that armed it once the timeout expires.
+pub struct Watchdog {
+    expired: Arc<AtomicBool>,
+    cancel: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+    previous: libc::sigaction,
+}
+
+impl Watchdog {
+    /// Arms a watchdog for the calling thread, which must be the thread
+    /// running the vCPU.
+    pub fn arm(timeout: Duration) -> Self {
+        // Install the handler without SA_RESTART so that KVM_RUN is not
+        // transparently restarted by the kernel. The previous disposition is
+        // restored once the watchdog is dropped.
+        let previous = unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = kick as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            libc::sigemptyset(&mut action.sa_mask);
+            action.sa_flags = 0;
+            let mut previous: libc::sigaction = std::mem::zeroed();
+            if libc::sigaction(KICK_SIGNAL, &action, &mut previous) != 0 {
+                panic!(
+                    "unable to install watchdog handler: {}",
+                    std::io::Error::last_os_error()
+                );
+            }
+            previous
+        };
+
+        let target = unsafe { libc::pthread_self() };
+        let expired = Arc::new(AtomicBool::new(false));
+        let (cancel, cancelled) = mpsc::channel::<()>();
+
+        let flag = expired.clone();
+        let thread = thread::spawn(move || {
+            // Dropping the Watchdog disconnects the channel.
+            if cancelled.recv_timeout(timeout) != Err(RecvTimeoutError::Timeout) {
+                return;
+            }
+
+            flag.store(true, Ordering::SeqCst);
+            loop {
+                unsafe {
+                    libc::pthread_kill(target, KICK_SIGNAL);
+                }
+
+                if cancelled.recv_timeout(KICK_INTERVAL) != Err(RecvTimeoutError::Timeout) {
+                    return;
+                }
+            }
+        });
+
+        Watchdog {
+            expired,
+            cancel: Some(cancel),
+            thread: Some(thread),
+            previous,
+        }
+    }
+
+    /// Whether the timeout has expired.
+    pub fn expired(&self) -> bool {
+        self.expired.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        self.cancel.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+
+        // No more kicks are sent, so the rest of the process no longer needs
+        // its system calls interrupted.
+        unsafe {
+            libc::sigaction(KICK_SIGNAL, &self.previous, std::ptr::null_mut());
+        }
+    }
+}
+
+/// Why the guest stopped running.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// The guest executed `hlt`.
+    Halted,
+
+    /// The guest issued the `EXIT` hypercall with the given result.
+    Exited(u64),
+
+    /// The guest ran for longer than `Limits::timeout`.
+    TimedOut,
+
+    /// The guest caused `Limits::max_exits` exits.
+    TooManyExits,
+}
+
+/// Port I/O accounting for a single port.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PortStats {
+    /// The number of reads (`in`) from the port, and the bytes read.
+    pub reads: u64,
+    pub bytes_read: u64,
+
+    /// The number of writes (`out`) to the port, and the bytes written.
+    pub writes: u64,
+    pub bytes_written: u64,
+}
+
+/// Exit accounting for a guest run.
+#[derive(Clone, Debug)]
+pub struct Stats {
+    started: Instant,
+    elapsed: Option<Duration>,
+    exits: u64,
+    reasons: BTreeMap<String, u64>,
+    ports: BTreeMap<u16, PortStats>,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Stats {
+            started: Instant::now(),
+            elapsed: None,
+            exits: 0,
+            reasons: BTreeMap::new(),
+            ports: BTreeMap::new(),
+        }
+    }
+}
+
+impl Stats {
+    /// Accounts for a single exit from `KVM_RUN`.
+    pub fn record(&mut self, reason: &Reason) {
+        self.exits += 1;
+
+        let label = match reason {
+            Reason::Halt => "halt".to_string(),
+            Reason::Io(ReasonIo::Out { port, data }) => {
+                let stats = self.ports.entry(*port).or_default();
+                stats.writes += 1;
+                stats.bytes_written += data.len() as u64;
+                "io-out".to_string()
+            }
+            Reason::Io(ReasonIo::In { port, data }) => {
+                let stats = self.ports.entry(*port).or_default();
+                stats.reads += 1;
+                stats.bytes_read += data.len() as u64;
+                "io-in".to_string()
+            }
+            other => {
+                // Use the variant name without any of its fields.
+                let name = format!("{:?}", other);
+                let end = name
+                    .find(|c: char| !c.is_alphanumeric())
+                    .unwrap_or(name.len());
+                name[..end].to_lowercase()
+            }
+        };
+
+        *self.reasons.entry(label).or_insert(0) += 1;
+    }
+
+    /// The total number of exits recorded so far.
+    pub fn exits(&self) -> u64 {
+        self.exits
+    }
+
+    /// Stops the run time clock.
+    pub fn finish(&mut self) {
+        self.elapsed = Some(self.started.elapsed());
+    }
+}
+
+impl Display for Stats {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let elapsed = self.elapsed.unwrap_or_else(|| self.started.elapsed());
+        writeln!(
+            f,
+            "         SERVER: Run Time {:?}, {} Exits",
+            elapsed, self.exits
+        )?;
+
+        for (reason, count) in self.reasons.iter() {
+            writeln!(f, "                 {:>8}: {}", reason, count)?;
+        }
+
+        for (port, stats) in self.ports.iter() {
+            writeln!(
+                f,
+                "                 port {:#06x}: {} reads ({} bytes), {} writes ({} bytes)",
+                port, stats.reads, stats.bytes_read, stats.writes, stats.bytes_written
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(values: &[&str]) -> impl Iterator<Item = String> {
+        values
+            .iter()
+            .map(|v| v.to_string())
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn limits() {
+        let mut limits = Limits::default();
+        assert_eq!(limits.timeout, Duration::from_secs(10));
+        assert_eq!(limits.max_exits, 1_000_000);
+
+        let mut rest = args(&["5", "next"]);
+        assert!(limits.parse_option("--timeout", &mut rest));
+        assert_eq!(limits.timeout, Duration::from_secs(5));
+        assert_eq!(rest.next().as_deref(), Some("next"));
+
+        assert!(limits.parse_option("--max-exits", &mut args(&[" 100 "])));
+        assert_eq!(limits.max_exits, 100);
+
+        let mut rest = args(&["oca.cert"]);
+        assert!(!limits.parse_option("--oca", &mut rest));
+        assert_eq!(rest.next().as_deref(), Some("oca.cert"));
+    }
+
+    #[test]
+    #[should_panic(expected = "--max-exits needs a number!")]
+    fn limits_need_a_value() {
+        Limits::default().parse_option("--max-exits", &mut args(&["many"]));
+    }
+
+    fn kick_disposition() -> libc::sighandler_t {
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            assert_eq!(
+                libc::sigaction(KICK_SIGNAL, std::ptr::null(), &mut action),
+                0
+            );
+            action.sa_sigaction
+        }
+    }
+
+    #[test]
+    fn watchdog() {
+        let before = kick_disposition();
+
+        let watchdog = Watchdog::arm(Duration::from_millis(20));
+        assert_ne!(kick_disposition(), before);
+        assert!(!watchdog.expired());
+        thread::sleep(Duration::from_millis(100));
+        assert!(watchdog.expired());
+
+        drop(watchdog);
+        assert_eq!(kick_disposition(), before);
+    }
+
+    #[test]
+    fn stats() {
+        let mut stats = Stats::default();
+        stats.record(&Reason::Io(ReasonIo::Out {
+            port: 0x3f8,
+            data: b"4",
+        }));
+        stats.record(&Reason::Io(ReasonIo::Out {
+            port: 0x3f8,
+            data: b"2\n",
+        }));
+        stats.record(&Reason::Io(ReasonIo::In {
+            port: 0x3f8,
+            data: &mut [0; 1],
+        }));
+        stats.record(&Reason::Io(ReasonIo::In {
+            port: 0x60,
+            data: &mut [0; 4],
+        }));
+        stats.record(&Reason::Halt);
+
+        assert_eq!(stats.exits(), 5);
+        assert_eq!(
+            stats.ports[&0x3f8],
+            PortStats {
+                reads: 1,
+                bytes_read: 1,
+                writes: 2,
+                bytes_written: 3,
+            }
+        );
+        assert_eq!(
+            stats.ports[&0x60],
+            PortStats {
+                reads: 1,
+                bytes_read: 4,
+                writes: 0,
+                bytes_written: 0,
+            }
+        );
+        assert!(!stats.ports.contains_key(&0x500));
+
+        let reasons: Vec<_> = stats.reasons.iter().map(|(r, n)| (&r[..], *n)).collect();
+        assert_eq!(reasons, [("halt", 1), ("io-in", 2), ("io-out", 2)]);
+    }
+}
@@ -0,0 +1,71 @@
+use attestation_protocol::transport::{Address, Timeouts};
+use serde::Deserialize;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+const LISTENER_CONN: &'static str = "localhost:1034";
+const ENCLAVE_CONN: &'static str = "localhost:1032";
+const WORKERS: usize = 8;
+
+/// The timeouts used unless configured otherwise. A tenant may stay idle between requests
+/// for up to the read timeout.
+const TIMEOUTS: Timeouts = Timeouts {
+    connect: Some(Duration::from_secs(10)),
+    read: Some(Duration::from_secs(60)),
+    write: Some(Duration::from_secs(60)),
+};
+
+/// The daemon's configuration, read from the TOML file given with `--config`, e.g.:
+///
+/// ```toml
+/// listen = "0.0.0.0:1034"
+/// enclave = "unix:/run/attestation-enclave.sock"
+/// workers = 16
+///
+/// [timeouts]
+/// connect = 5
+/// read = 30
+/// write = 30
+/// ```
+///
+/// Every setting is optional, and can be overridden on the command line. The timeouts
+/// default to 10 seconds to connect, and 60 seconds to read or write.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The address the daemon listens on, for tenants and for enclaves that need a Quote.
+    pub listen: Address,
+
+    /// The address of the enclave.
+    pub enclave: Address,
+
+    /// The number of connections the daemon handles at once. Further connections wait
+    /// until one of those is closed.
+    pub workers: usize,
+
+    /// The timeouts of the daemon's connections to the enclave. The read and write timeouts
+    /// also apply to the connections of tenants, so that an idle tenant does not hold on to
+    /// a worker for ever. Timeouts that are not configured keep their defaults.
+    pub timeouts: Timeouts,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            listen: Address::Tcp(LISTENER_CONN.to_string()),
+            enclave: Address::Tcp(ENCLAVE_CONN.to_string()),
+            workers: WORKERS,
+            timeouts: TIMEOUTS,
+        }
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let mut config: Config = toml::from_str(&fs::read_to_string(path)?)?;
+        config.timeouts = TIMEOUTS.overridden_by(config.timeouts);
+        Ok(config)
+    }
+}
@@ -1,42 +1,433 @@
-use std::error::Error;
-use std::io::Write;
-use std::net::{TcpListener, TcpStream};
+mod config;
+mod quote_provider;
+mod sealed_store;
+mod worker_pool;
 
-const LISTENER_CONN: &'static str = "localhost:1034";
-const ENCLAVE_CONN: &'static str = "localhost:1032";
+use attestation_protocol::transport::{self, Listener, Stream, Timeouts};
+use attestation_protocol::{self as protocol, Message};
+use attestation_sim::{Platform, QuotingEnclave};
+use config::Config;
+use quote_provider::{QuoteProvider, SimQuoteProvider};
+use sealed_store::SealedStore;
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
+use worker_pool::WorkerPool;
 
+/// With `--dump-quote DIR`, the daemon also writes every Quote it generates to DIR, so
+/// that it can be verified again later with `attestation-tenant --quote FILE`.
+///
+/// With `--simulate DIR`, the daemon uses a simulated Quoting Enclave instead of Intel's, so
+/// that attestation can run without SGX hardware. The simulated Quoting Enclave is certified
+/// by a test PKI generated on startup, and the collateral a tenant needs to verify its Quotes
+/// (PCK cert chain, CRLs, TCB Info, TCB signing chain and QE Identity) is written to DIR.
+///
+/// With `--sealed-dir DIR`, the secrets the enclave sealed are stored in DIR, and handed
+/// back to the enclave after both have restarted. Without it, they are only kept in memory.
+///
+/// The addresses and timeouts are read from the TOML file given with `--config FILE` (see
+/// `Config`), and can be overridden with `--listen ADDR`, `--enclave ADDR`,
+/// `--connect-timeout SECS`, `--read-timeout SECS` and `--write-timeout SECS`. Addresses
+/// are TCP (`HOST:PORT`), Unix domain socket (`unix:PATH`) or vsock (`vsock:CID:PORT`)
+/// addresses. The number of connections handled at once can be set with `--workers N`.
 fn main() -> Result<(), Box<dyn Error>> {
+    let mut args = env::args().skip(1);
+    let mut dump_dir = None;
+    let mut simulation_dir = None;
+    let mut sealed_dir = None;
+    let mut config_file = None;
+    let mut listen = None;
+    let mut enclave = None;
+    let mut workers = None;
+    let mut timeouts = Timeouts::default();
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--config" => {
+                config_file = Some(PathBuf::from(
+                    args.next()
+                        .expect("--config requires the path of a configuration file."),
+                ))
+            }
+            "--listen" => {
+                listen = Some(
+                    args.next()
+                        .expect("--listen requires an address.")
+                        .parse()?,
+                )
+            }
+            "--enclave" => {
+                enclave = Some(
+                    args.next()
+                        .expect("--enclave requires an address.")
+                        .parse()?,
+                )
+            }
+            "--workers" => {
+                workers = Some(
+                    args.next()
+                        .expect("--workers requires a number of connections.")
+                        .parse()?,
+                )
+            }
+            "--connect-timeout" | "--read-timeout" | "--write-timeout" => {
+                let timeout = Some(transport::parse_timeout(
+                    &args.next().expect("Timeouts require a number of seconds."),
+                )?);
+                match &arg[..] {
+                    "--connect-timeout" => timeouts.connect = timeout,
+                    "--read-timeout" => timeouts.read = timeout,
+                    _ => timeouts.write = timeout,
+                }
+            }
+            "--dump-quote" => {
+                dump_dir = Some(PathBuf::from(
+                    args.next()
+                        .expect("--dump-quote requires the path of a directory."),
+                ))
+            }
+            "--simulate" => {
+                simulation_dir = Some(PathBuf::from(
+                    args.next()
+                        .expect("--simulate requires the path of a directory."),
+                ))
+            }
+            "--sealed-dir" => {
+                sealed_dir = Some(PathBuf::from(
+                    args.next()
+                        .expect("--sealed-dir requires the path of a directory."),
+                ))
+            }
+            _ => panic!("Unknown argument: {}", arg),
+        }
+    }
+
+    let quote_provider: Box<dyn QuoteProvider + Send> = match simulation_dir {
+        Some(dir) => {
+            let qe = QuotingEnclave::new(Platform::default())?;
+            qe.write_collateral(&dir)?;
+            println!(
+                "Simulating the Quoting Enclave. Its collateral was written to {}",
+                dir.display()
+            );
+            Box::new(SimQuoteProvider(qe))
+        }
+        None => dcap_quote_provider()?,
+    };
+    let sealed_store = SealedStore::new(sealed_dir)?;
+
+    let mut config = match config_file {
+        Some(path) => Config::load(&path)?,
+        None => Config::default(),
+    };
+    config.listen = listen.unwrap_or(config.listen);
+    config.enclave = enclave.unwrap_or(config.enclave);
+    config.workers = workers.unwrap_or(config.workers);
+    config.timeouts = config.timeouts.overridden_by(timeouts);
+
+    if config.workers == 0 {
+        return Err("The daemon needs at least one worker.".into());
+    }
+
+    let listener = Listener::bind(&config.listen)?;
+    let pool = WorkerPool::new(config.workers);
+    let daemon = Arc::new(Daemon {
+        quote_provider: Mutex::new(quote_provider),
+        sealed_store: Mutex::new(sealed_store),
+        dump_dir,
+        config,
+    });
+
     println!(
-        "Daemon listening for attestation request on {}... ",
-        LISTENER_CONN
+        "Daemon listening for attestation request on {}, with {} workers... ",
+        daemon.config.listen, daemon.config.workers
     );
 
-    // The attestation daemon handles each incoming connection from a tenant. The tenant, by
-    // connecting, is requesting an attestation of the enclave.
-    for incoming_tenant_stream in TcpListener::bind(LISTENER_CONN)?.incoming() {
-        // The attestation daemon retrieves the Quoting Enclave's Target Info from the CPU and
-        // sends the Quoting Enclave's Target Info to the enclave. This Target Info will be
-        // used as the target for the enclave's attestation Report.
-        let qe_ti = dcap_ql::target_info().expect("Could not retrieve QE target info.");
+    // The attestation daemon handles each incoming connection from a tenant, or from an
+    // enclave that needs a Quote, on a worker of its own. Whatever goes wrong with one
+    // connection is reported to its peer, and only ends that connection.
+    for incoming_tenant_stream in listener.incoming() {
+        let tenant_stream = match incoming_tenant_stream {
+            Ok(tenant_stream) => tenant_stream,
+            Err(e) => {
+                println!("\nCould not accept a connection: {}", e);
+                continue;
+            }
+        };
+        let daemon = Arc::clone(&daemon);
+        pool.execute(move || {
+            if let Err(e) = serve(&daemon, tenant_stream) {
+                println!("\nConnection closed: {}", e);
+            }
+        });
+    }
+    Ok(())
+}
 
-        // Serialize the Target Info onto the stream to the enclave
-        let mut enclave_stream = TcpStream::connect(ENCLAVE_CONN)?;
-        serde_json::to_writer(&mut enclave_stream, &qe_ti)?;
-        enclave_stream.shutdown(std::net::Shutdown::Write)?;
+/// The state the workers share.
+struct Daemon {
+    /// The Quoting Enclave, which generates one Quote at a time.
+    quote_provider: Mutex<Box<dyn QuoteProvider + Send>>,
+    sealed_store: Mutex<SealedStore>,
+    dump_dir: Option<PathBuf>,
+    config: Config,
+}
 
-        // The attestation daemon receives the Report back from the attesting enclave.
-        let report: sgx_isa::Report = serde_json::from_reader(&mut enclave_stream)?;
+impl Daemon {
+    fn quote_provider(&self) -> MutexGuard<'_, Box<dyn QuoteProvider + Send>> {
+        lock(&self.quote_provider)
+    }
+
+    fn sealed_store(&self) -> MutexGuard<'_, SealedStore> {
+        lock(&self.sealed_store)
+    }
+}
+
+/// This takes the lock even if a worker panicked while holding it: neither the Quoting
+/// Enclave nor the sealed store is left half-updated by a panic, and a panic must not take
+/// attestation down for the other connections.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
 
-        // The attestation daemon gets a Quote from the Quoting Enclave for the Report.
-        // The Quoting Enclave verifies the Report's MAC as a prerequisite for generating
-        // the Quote. The Quote is signed with the Quoting Enclave's Attestation Key.
-        let quote = dcap_ql::quote(&report).expect("Could not generate quote.");
+/// This handles the requests of a tenant, or of an enclave that needs a Quote, until it
+/// closes the connection. A tenant may send any number of requests over its connection. A
+/// request that cannot be served is answered with `Message::Error`, and the connection is
+/// only closed if it cannot be read or written any more.
+fn serve(daemon: &Daemon, mut tenant_stream: Stream) -> Result<(), protocol::Error> {
+    tenant_stream.set_timeouts(&daemon.config.timeouts)?;
 
-        // The attestation daemon sends the Quote to the tenant.
-        let mut tenant_stream = incoming_tenant_stream?;
-        tenant_stream.write(&quote)?;
+    // The connection to the enclave is opened on the first request and reused for the
+    // following ones. It is dropped, and reopened on the next request, if an exchange
+    // with the enclave fails.
+    let mut enclave_stream = None;
 
-        println!("\nQuote successfully generated and sent to tenant...");
+    loop {
+        let request = match protocol::recv(&mut tenant_stream) {
+            Ok(Some(request)) => request,
+            Ok(None) => return Ok(()),
+
+            // The whole message was read, so the next one can still be: the tenant is told
+            // what was wrong with this one, and may carry on.
+            Err(e @ protocol::Error::Json(_)) => {
+                println!("\nInvalid request: {}", e);
+                protocol::send(
+                    &mut tenant_stream,
+                    &Message::Error {
+                        message: e.to_string(),
+                    },
+                )?;
+                continue;
+            }
+
+            // Where the next message starts is unknown: the tenant is told why, if it is
+            // still listening, and the connection is closed.
+            Err(e @ protocol::Error::Version(_)) | Err(e @ protocol::Error::TooLarge(_)) => {
+                let _ = protocol::send(
+                    &mut tenant_stream,
+                    &Message::Error {
+                        message: e.to_string(),
+                    },
+                );
+                return Err(e);
+            }
+            Err(e) => return Err(e),
+        };
+
+        let reply = handle(daemon, &mut enclave_stream, request);
+
+        // The attestation daemon sends the Quote, the enclave's reply, or the reason it
+        // could not get one, to the tenant.
+        protocol::send(&mut tenant_stream, &reply)?;
     }
-    Ok(())
+}
+
+/// This serves a single request, and returns the reply to send back.
+fn handle(daemon: &Daemon, enclave_stream: &mut Option<Stream>, request: Message) -> Message {
+    match request {
+        // The tenant sends a random nonce with its request. It is passed on to the
+        // enclave, which binds it into its Report, so that the tenant can tell a fresh
+        // Quote from a replayed one.
+        Message::AttestationRequest { nonce } => match attest(daemon, enclave_stream, nonce) {
+            Ok(reply) => {
+                if let (Some(dir), Message::Quote { quote, .. }) = (&daemon.dump_dir, &reply) {
+                    match dump_quote(dir, quote) {
+                        Ok(path) => println!("Quote written to {}", path.display()),
+                        Err(e) => println!("Could not write Quote to {}: {}", dir.display(), e),
+                    }
+                }
+                println!("\nQuote successfully generated and sent to tenant...");
+                reply
+            }
+            Err(e) => {
+                println!("\nAttestation request failed: {}", e);
+                *enclave_stream = None;
+                Message::Error {
+                    message: e.to_string(),
+                }
+            }
+        },
+
+        // An enclave may also have its own Reports quoted, e.g. to embed the Quote in its
+        // RA-TLS certificate. It first needs the Quoting Enclave's Target Info, to target
+        // the Report at it.
+        Message::QeTargetInfoRequest => match daemon.quote_provider().target_info() {
+            Ok(target_info) => Message::QeTargetInfo { target_info },
+            Err(e) => Message::Error {
+                message: e.to_string(),
+            },
+        },
+        Message::QuoteRequest { report } => match daemon.quote_provider().quote(&report) {
+            Ok(quote) => {
+                println!("\nQuote successfully generated and sent to enclave...");
+                Message::Quote {
+                    quote,
+                    enclave_data: Vec::new(),
+                }
+            }
+            Err(e) => Message::Error {
+                message: e.to_string(),
+            },
+        },
+
+        // Once the tenant has verified the Quote, it opens a secure channel with the
+        // enclave. The channel's messages are relayed to the enclave that was just
+        // attested, over the same connection, and its replies back to the tenant. The
+        // daemon cannot read or alter the channel.
+        message @ Message::KeyExchange { .. } | message @ Message::Encrypted { .. } => {
+            match relay(daemon, enclave_stream, &message) {
+                Ok(reply) => reply,
+                Err(e) => {
+                    println!("\nCould not relay {} to the enclave: {}", message.name(), e);
+                    *enclave_stream = None;
+                    Message::Error {
+                        message: e.to_string(),
+                    }
+                }
+            }
+        }
+        other => Message::Error {
+            message: protocol::Error::Unexpected(other.name()).to_string(),
+        },
+    }
+}
+
+/// This has the enclave produce a Report bound to the tenant's nonce, and has the Quoting
+/// Enclave turn it into a Quote.
+fn attest(
+    daemon: &Daemon,
+    enclave_stream: &mut Option<Stream>,
+    nonce: [u8; 32],
+) -> Result<Message, Box<dyn Error>> {
+    // The attestation daemon retrieves the Quoting Enclave's Target Info and sends it to
+    // the enclave. This Target Info will be used as the target for the enclave's
+    // attestation Report.
+    let qe_ti = daemon.quote_provider().target_info()?;
+
+    if enclave_stream.is_none() {
+        *enclave_stream = Some(connect_enclave(daemon)?);
+    }
+    let enclave_stream = enclave_stream
+        .as_mut()
+        .expect("Enclave connection was just opened.");
+
+    protocol::send(
+        enclave_stream,
+        &Message::TargetInfo {
+            target_info: qe_ti,
+            nonce,
+        },
+    )?;
+
+    // The attestation daemon receives the Report back from the attesting enclave.
+    let (report, enclave_data) = match protocol::recv_reply(enclave_stream)? {
+        Message::Report {
+            report,
+            enclave_data,
+        } => (report, enclave_data),
+        other => return Err(protocol::Error::Unexpected(other.name()).into()),
+    };
+
+    // The attestation daemon gets a Quote from the Quoting Enclave for the Report.
+    // The Quoting Enclave verifies the Report's MAC as a prerequisite for generating
+    // the Quote. The Quote is signed with the Quoting Enclave's Attestation Key.
+    let quote = daemon.quote_provider().quote(&report)?;
+
+    Ok(Message::Quote {
+        quote,
+        enclave_data,
+    })
+}
+
+/// This opens a connection to the enclave, and hands it back the secrets it sealed, if any.
+fn connect_enclave(daemon: &Daemon) -> Result<Stream, Box<dyn Error>> {
+    let mut enclave_stream = Stream::connect(&daemon.config.enclave, &daemon.config.timeouts)?;
+
+    let blobs = daemon.sealed_store().blobs();
+    if !blobs.is_empty() {
+        let count = blobs.len();
+        protocol::send(&mut enclave_stream, &Message::Unseal { blobs })?;
+        match protocol::recv_reply(&mut enclave_stream)? {
+            Message::Unsealed { names } => println!(
+                "\nThe enclave unsealed {} of its {} sealed secrets...",
+                names.len(),
+                count
+            ),
+            other => return Err(protocol::Error::Unexpected(other.name()).into()),
+        }
+    }
+    Ok(enclave_stream)
+}
+
+/// This forwards a secure channel message to the enclave, and returns its reply, which may
+/// be `Message::Error`. The enclave must have been attested over the connection. The
+/// secrets the enclave seals on the way are stored.
+fn relay(
+    daemon: &Daemon,
+    enclave_stream: &mut Option<Stream>,
+    message: &Message,
+) -> Result<Message, Box<dyn Error>> {
+    let enclave_stream = enclave_stream
+        .as_mut()
+        .ok_or("No enclave was attested over this connection.")?;
+    protocol::send(enclave_stream, message)?;
+    loop {
+        match protocol::recv(enclave_stream)? {
+            Some(Message::Sealed { name, blob }) => match daemon.sealed_store().store(name, blob) {
+                Ok(()) => println!("\nSealed secret stored for the enclave..."),
+                Err(e) => println!("\nCould not store the enclave's sealed secret: {}", e),
+            },
+            Some(reply) => return Ok(reply),
+            None => return Err("The enclave closed the connection.".into()),
+        }
+    }
+}
+
+#[cfg(feature = "dcap")]
+fn dcap_quote_provider() -> Result<Box<dyn QuoteProvider + Send>, Box<dyn Error>> {
+    Ok(Box::new(quote_provider::DcapQuoteProvider))
+}
+
+#[cfg(not(feature = "dcap"))]
+fn dcap_quote_provider() -> Result<Box<dyn QuoteProvider + Send>, Box<dyn Error>> {
+    Err("The daemon was built without the dcap feature; run it with --simulate DIR.".into())
+}
+
+/// This writes the raw binary Quote to a new file in dir, named after the time it was
+/// generated.
+fn dump_quote(dir: &Path, quote: &[u8]) -> std::io::Result<PathBuf> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let path = dir.join(format!(
+        "quote-{}-{:09}.dat",
+        now.as_secs(),
+        now.subsec_nanos()
+    ));
+    fs::write(&path, quote)?;
+    Ok(path)
 }
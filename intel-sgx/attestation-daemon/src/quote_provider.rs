@@ -0,0 +1,42 @@
+use sgx_isa::{Report, Targetinfo};
+use std::error::Error;
+
+/// A Quoting Enclave, which turns the Reports of enclaves targeting it into Quotes.
+pub trait QuoteProvider {
+    /// The Quoting Enclave's Target Info, which the enclave targets its Report at.
+    fn target_info(&self) -> Result<Targetinfo, Box<dyn Error>>;
+
+    /// This has the Quoting Enclave verify the Report and sign it into a Quote.
+    fn quote(&self, report: &Report) -> Result<Vec<u8>, Box<dyn Error>>;
+}
+
+/// Intel's Quoting Enclave, reached through the DCAP Quoting Library. This needs SGX
+/// hardware, the DCAP driver and the DCAP components to be installed.
+#[cfg(feature = "dcap")]
+pub struct DcapQuoteProvider;
+
+#[cfg(feature = "dcap")]
+impl QuoteProvider for DcapQuoteProvider {
+    fn target_info(&self) -> Result<Targetinfo, Box<dyn Error>> {
+        dcap_ql::target_info()
+            .map_err(|e| format!("Could not retrieve QE target info: {}", e).into())
+    }
+
+    fn quote(&self, report: &Report) -> Result<Vec<u8>, Box<dyn Error>> {
+        dcap_ql::quote(report).map_err(|e| format!("Could not generate quote: {}", e).into())
+    }
+}
+
+/// The simulated Quoting Enclave of attestation-sim, which runs without SGX. Its Quotes are
+/// only trusted by tenants configured with its collateral.
+pub struct SimQuoteProvider(pub attestation_sim::QuotingEnclave);
+
+impl QuoteProvider for SimQuoteProvider {
+    fn target_info(&self) -> Result<Targetinfo, Box<dyn Error>> {
+        Ok(self.0.target_info())
+    }
+
+    fn quote(&self, report: &Report) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.0.quote(report)
+    }
+}
@@ -0,0 +1,53 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::PathBuf;
+
+/// The secrets the enclave sealed, which the daemon stores on its behalf: the enclave has no
+/// storage of its own. They are kept in memory, so that they survive restarts of the
+/// enclave, and also in a directory if one is given, so that they survive restarts of the
+/// daemon. The daemon cannot read them.
+pub struct SealedStore {
+    blobs: BTreeMap<String, Vec<u8>>,
+    dir: Option<PathBuf>,
+}
+
+impl SealedStore {
+    /// This creates the store, with the blobs already stored in dir, if any.
+    pub fn new(dir: Option<PathBuf>) -> Result<Self, Box<dyn Error>> {
+        let mut blobs = BTreeMap::new();
+        if let Some(dir) = &dir {
+            fs::create_dir_all(dir)?;
+            for entry in fs::read_dir(dir)? {
+                let path = entry?.path();
+                if path.extension() != Some(OsStr::new("sealed")) {
+                    continue;
+                }
+                let name = path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .and_then(|stem| hex::decode(stem).ok())
+                    .and_then(|name| String::from_utf8(name).ok())
+                    .ok_or(format!("{} is not a sealed secret.", path.display()))?;
+                blobs.insert(name, fs::read(&path)?);
+            }
+        }
+        Ok(SealedStore { blobs, dir })
+    }
+
+    /// This stores the blob, replacing any earlier blob of the same name. The file is named
+    /// after the hex-encoded name, which may contain any character.
+    pub fn store(&mut self, name: String, blob: Vec<u8>) -> Result<(), Box<dyn Error>> {
+        if let Some(dir) = &self.dir {
+            fs::write(dir.join(format!("{}.sealed", hex::encode(&name))), &blob)?;
+        }
+        self.blobs.insert(name, blob);
+        Ok(())
+    }
+
+    /// All the stored blobs.
+    pub fn blobs(&self) -> Vec<Vec<u8>> {
+        self.blobs.values().cloned().collect()
+    }
+}
@@ -0,0 +1,82 @@
+use attestation_protocol::transport::{Address, Timeouts};
+use serde::Deserialize;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+const LISTENER_ADDR: &'static str = "localhost:1032";
+const RA_TLS_ADDR: &'static str = "localhost:1036";
+const DAEMON_ADDR: &'static str = "localhost:1034";
+/// Inside SGX, the enclave's `threads` (see its `Cargo.toml`) must leave room for the
+/// workers, along with the main thread and the RA-TLS server's.
+const WORKERS: usize = 8;
+
+/// The timeouts used unless configured otherwise. The daemon keeps its connection open while
+/// its tenant is idle, for up to its own read timeout of 60 seconds by default: the
+/// enclave waits longer, so that the daemon closes the connection first.
+const TIMEOUTS: Timeouts = Timeouts {
+    connect: Some(Duration::from_secs(10)),
+    read: Some(Duration::from_secs(120)),
+    write: Some(Duration::from_secs(60)),
+};
+
+/// The enclave's configuration, read from the TOML file given with `--config`, e.g.:
+///
+/// ```toml
+/// listen = "vsock:4294967295:1032"
+/// ra_tls_listen = "0.0.0.0:1036"
+/// daemon = "vsock:2:1034"
+/// workers = 4
+///
+/// [timeouts]
+/// connect = 5
+/// read = 300
+/// ```
+///
+/// Every setting is optional, and can be overridden on the command line. The timeouts
+/// default to 10 seconds to connect, 120 seconds to read and 60 seconds to write. Inside SGX,
+/// the enclave has no file system to read the file from, and must be configured on the
+/// command line; it can only use TCP.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The address the enclave listens on for the daemon, and for enclaves attesting it
+    /// locally.
+    pub listen: Address,
+
+    /// The address the enclave serves RA-TLS on, with `--ra-tls`.
+    pub ra_tls_listen: Address,
+
+    /// The address of the daemon, which quotes the enclave's RA-TLS certificate.
+    pub daemon: Address,
+
+    /// The number of the daemon's connections the enclave serves at once. Further
+    /// connections wait until one of those is closed.
+    pub workers: usize,
+
+    /// The timeouts of the enclave's connections: those it accepts from the daemon, and
+    /// those it opens to the daemon and to the enclaves it attests. Timeouts that are not
+    /// configured keep their defaults.
+    pub timeouts: Timeouts,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            listen: Address::Tcp(LISTENER_ADDR.to_string()),
+            ra_tls_listen: Address::Tcp(RA_TLS_ADDR.to_string()),
+            daemon: Address::Tcp(DAEMON_ADDR.to_string()),
+            workers: WORKERS,
+            timeouts: TIMEOUTS,
+        }
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let mut config: Config = toml::from_str(&fs::read_to_string(path)?)?;
+        config.timeouts = TIMEOUTS.overridden_by(config.timeouts);
+        Ok(config)
+    }
+}
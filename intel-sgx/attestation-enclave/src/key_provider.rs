@@ -0,0 +1,46 @@
+use sgx_isa::Keyrequest;
+use std::error::Error;
+
+/// The source of the enclave's keys: its SEAL keys, and the REPORT key with which the
+/// Reports targeted at it are MACed.
+pub trait KeyProvider {
+    /// The enclave's current ISVSVN and CPUSVN, for which data is sealed.
+    fn svn(&self) -> Result<(u16, [u8; 16]), Box<dyn Error>>;
+
+    /// This derives the key described by the request.
+    fn key(&self, request: &Keyrequest) -> Result<[u8; 16], Box<dyn Error>>;
+}
+
+/// Keys derived by the CPU with ENCLU[EGETKEY], which only works inside an SGX enclave.
+#[cfg(target_env = "sgx")]
+pub struct SgxKeyProvider;
+
+#[cfg(target_env = "sgx")]
+impl KeyProvider for SgxKeyProvider {
+    fn svn(&self) -> Result<(u16, [u8; 16]), Box<dyn Error>> {
+        let report = sgx_isa::Report::for_self();
+        Ok((report.isvsvn, report.cpusvn))
+    }
+
+    fn key(&self, request: &Keyrequest) -> Result<[u8; 16], Box<dyn Error>> {
+        request
+            .egetkey()
+            .map_err(|e| format!("EGETKEY failed: {:?}", e).into())
+    }
+}
+
+/// Keys of a simulated enclave, derived from the simulated platform's secret of
+/// attestation-sim. They protect nothing, as that secret is public.
+#[cfg(not(target_env = "sgx"))]
+pub struct SimKeyProvider(pub attestation_sim::EnclaveIdentity);
+
+#[cfg(not(target_env = "sgx"))]
+impl KeyProvider for SimKeyProvider {
+    fn svn(&self) -> Result<(u16, [u8; 16]), Box<dyn Error>> {
+        Ok((self.0.isvsvn, self.0.cpusvn))
+    }
+
+    fn key(&self, request: &Keyrequest) -> Result<[u8; 16], Box<dyn Error>> {
+        self.0.egetkey(request)
+    }
+}
@@ -0,0 +1,211 @@
+//! Local attestation between enclaves on the same platform.
+//!
+//! An enclave proves its identity to another enclave of the same platform with a Report
+//! targeted at it. The verifying enclave sends its own Target Info and a fresh nonce in
+//! `Message::TargetInfo`, as the daemon does on behalf of the Quoting Enclave, and the
+//! attested enclave answers with `Message::Report`, whose ReportData binds the nonce and the
+//! attested enclave's data. The CPU MACs the Report with the verifier's REPORT key, which
+//! only the verifier can derive with EGETKEY: a valid MAC proves that the Report was produced
+//! on the same platform by an enclave measured as it reports. Neither the Quoting Enclave
+//! nor any collateral is involved.
+
+use crate::key_provider::KeyProvider;
+use crate::report_provider::ReportProvider;
+use aes::Aes128;
+use attestation_protocol::{self as protocol, Message};
+use cmac::{Cmac, Mac, NewMac};
+use rand_core::{OsRng, RngCore};
+use sgx_isa::{AttributesFlags, Keyname, Keyrequest, Report};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::io::{Read, Write};
+
+/// The Report body, which the MAC covers, is the Report without its KEYID and MAC.
+const REPORT_BODY_SIZE: usize = 384;
+
+/// An enclave attested locally: its verified Report, and the data it bound into it.
+pub struct LocalAttestation {
+    pub report: Report,
+    pub enclave_data: Vec<u8>,
+}
+
+impl Display for LocalAttestation {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "MRENCLAVE {}, MRSIGNER {}, ISVPRODID {}, ISVSVN {}",
+            hex(&self.report.mrenclave),
+            hex(&self.report.mrsigner),
+            self.report.isvprodid,
+            self.report.isvsvn
+        )?;
+        if self
+            .report
+            .attributes
+            .flags
+            .contains(AttributesFlags::DEBUG)
+        {
+            write!(f, " (DEBUG)")?;
+        }
+        write!(f, ", with data {}", hex(&self.enclave_data))
+    }
+}
+
+/// This attests the enclave on the other end of the stream locally, as the enclave whose
+/// Reports and keys the providers give. The caller decides whether it trusts the identity
+/// the verified Report describes.
+pub fn attest<S: Read + Write>(
+    stream: &mut S,
+    report_provider: &dyn ReportProvider,
+    key_provider: &dyn KeyProvider,
+) -> Result<LocalAttestation, Box<dyn Error>> {
+    let mut nonce = [0u8; 32];
+    OsRng.fill_bytes(&mut nonce);
+    let target_info = report_provider.target_info()?;
+    protocol::send(stream, &Message::TargetInfo { target_info, nonce })?;
+
+    let (report, enclave_data) = match protocol::recv_reply(stream)? {
+        Message::Report {
+            report,
+            enclave_data,
+        } => (report, enclave_data),
+        other => return Err(protocol::Error::Unexpected(other.name()).into()),
+    };
+
+    verify_report(key_provider, &report)?;
+    if report.reportdata[..] != protocol::report_data(&nonce, &enclave_data)[..] {
+        return Err("The Report does not bind the nonce and the enclave's data.".into());
+    }
+
+    Ok(LocalAttestation {
+        report,
+        enclave_data,
+    })
+}
+
+/// This checks that the Report was targeted at this enclave and has not been altered, by
+/// recomputing its MAC, the AES-128-CMAC of its body, under this enclave's REPORT key.
+pub fn verify_report(
+    key_provider: &dyn KeyProvider,
+    report: &Report,
+) -> Result<(), Box<dyn Error>> {
+    let report_key = key_provider.key(&Keyrequest {
+        keyname: Keyname::Report as u16,
+        keyid: report.keyid,
+        ..Default::default()
+    })?;
+
+    let mut mac = Cmac::<Aes128>::new_varkey(&report_key).expect("The REPORT key is 128-bit.");
+    mac.update(&report.as_ref()[..REPORT_BODY_SIZE]);
+    mac.verify(&report.mac)
+        .map_err(|_| "The Report was not targeted at this enclave, or was altered.".into())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key_provider::SimKeyProvider;
+    use crate::report_provider::SimReportProvider;
+    use attestation_sim::EnclaveIdentity;
+    use std::os::unix::net::UnixStream;
+    use std::thread::{self, JoinHandle};
+
+    fn verifier() -> EnclaveIdentity {
+        EnclaveIdentity {
+            mrenclave: [0x7a; 32],
+            ..Default::default()
+        }
+    }
+
+    /// The attested enclave, which answers a single request for a Report. A replaying
+    /// enclave binds a nonce of its own choosing instead of the verifier's.
+    fn peer(
+        identity: EnclaveIdentity,
+        mut stream: UnixStream,
+        replay: bool,
+    ) -> JoinHandle<Result<(), protocol::Error>> {
+        thread::spawn(move || match protocol::recv_reply(&mut stream)? {
+            Message::TargetInfo { target_info, nonce } => {
+                let nonce = if replay { [0; 32] } else { nonce };
+                let enclave_data = b"peer public key".to_vec();
+                let report_data = protocol::report_data(&nonce, &enclave_data);
+                let report = identity
+                    .report(&target_info, &report_data)
+                    .expect("The simulated enclave reports to any target.");
+                let reply = Message::Report {
+                    report,
+                    enclave_data,
+                };
+                protocol::send(&mut stream, &reply)
+            }
+            other => Err(protocol::Error::Unexpected(other.name())),
+        })
+    }
+
+    fn attest_peer(
+        identity: EnclaveIdentity,
+        replay: bool,
+    ) -> Result<LocalAttestation, Box<dyn Error>> {
+        let (mut stream, peer_stream) = UnixStream::pair().unwrap();
+        let peer = peer(identity, peer_stream, replay);
+        let attestation = attest(
+            &mut stream,
+            &SimReportProvider(verifier()),
+            &SimKeyProvider(verifier()),
+        );
+        peer.join().expect("The peer does not panic.")?;
+        attestation
+    }
+
+    #[test]
+    fn peer_is_attested() {
+        let identity = EnclaveIdentity {
+            mrenclave: [0x3c; 32],
+            isvsvn: 4,
+            ..Default::default()
+        };
+        let attestation = attest_peer(identity.clone(), false).unwrap();
+        assert_eq!(attestation.report.mrenclave, identity.mrenclave);
+        assert_eq!(attestation.report.mrsigner, identity.mrsigner);
+        assert_eq!(attestation.report.isvsvn, 4);
+        assert_eq!(attestation.enclave_data, b"peer public key");
+    }
+
+    #[test]
+    fn replayed_report_is_rejected() {
+        assert!(attest_peer(EnclaveIdentity::default(), true).is_err());
+    }
+
+    #[test]
+    fn report_targeted_at_another_enclave_is_rejected() {
+        let report = EnclaveIdentity::default()
+            .report(&verifier().target_info(), &[0; 64])
+            .unwrap();
+        assert!(verify_report(&SimKeyProvider(verifier()), &report).is_ok());
+
+        let other = EnclaveIdentity {
+            mrenclave: [0x1d; 32],
+            ..Default::default()
+        };
+        assert!(verify_report(&SimKeyProvider(other), &report).is_err());
+    }
+
+    #[test]
+    fn altered_report_is_rejected() {
+        let report = EnclaveIdentity::default()
+            .report(&verifier().target_info(), &[0; 64])
+            .unwrap();
+
+        let mut altered = report.clone();
+        altered.mrenclave[0] ^= 0x01;
+        assert!(verify_report(&SimKeyProvider(verifier()), &altered).is_err());
+
+        let mut altered = report;
+        altered.isvsvn += 1;
+        assert!(verify_report(&SimKeyProvider(verifier()), &altered).is_err());
+    }
+}
@@ -1,35 +1,441 @@
-use sgx_isa::Report;
-use std::error::Error;
-use std::net::TcpListener;
+mod config;
+mod key_provider;
+mod local_attestation;
+mod ra_tls;
+mod report_provider;
+mod seal;
+mod worker_pool;
 
-const LISTENER_ADDR: &'static str = "localhost:1032";
+use attestation_protocol::channel::{KeyExchange, Role, SecureChannel};
+use attestation_protocol::provisioning::{Receipt, SecretStore};
+use attestation_protocol::transport::{self, Listener, Stream, Timeouts};
+use attestation_protocol::{self as protocol, Message};
+use config::Config;
+use key_provider::KeyProvider;
+use report_provider::ReportProvider;
+use sgx_isa::Keypolicy;
+use std::env;
+use std::error::Error;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use worker_pool::WorkerPool;
 
+/// With `--ra-tls`, the enclave also serves TLS with an RA-TLS certificate,
+/// for which it gets a Quote from the attestation daemon on startup: the daemon must be
+/// running first.
+///
+/// The enclave seals the secrets provisioned to it, and hands them to the daemon to store.
+/// With `--seal-policy mrsigner`, they are sealed so that later versions of the enclave, by
+/// the same signer, can unseal them; by default (`--seal-policy mrenclave`), only the same
+/// enclave can.
+///
+/// With `--attest-peer ADDR`, the enclave instead attests the enclave listening on ADDR
+/// locally, i.e. verifies a Report it targeted at this enclave, prints its identity, and
+/// exits.
+///
+/// The addresses and timeouts are read from the TOML file given with `--config FILE` (see
+/// `Config`), and can be overridden with `--listen ADDR`, `--ra-tls-listen ADDR`,
+/// `--daemon ADDR`, `--connect-timeout SECS`, `--read-timeout SECS` and
+/// `--write-timeout SECS`. Addresses are TCP (`HOST:PORT`), Unix domain socket
+/// (`unix:PATH`) or vsock (`vsock:CID:PORT`) addresses. The number of connections served at
+/// once can be set with `--workers N`.
 fn main() -> Result<(), Box<dyn Error>> {
-    println!("\nListening on {}....\n", LISTENER_ADDR);
-
-    // The enclave handles each incoming connection from attestation daemon.
-    for stream in TcpListener::bind(LISTENER_ADDR).unwrap().incoming() {
-        let mut stream = stream?;
-
-        // The enclave receives the identity of the Quoting Enclave from the
-        // attestation daemon, in the form of a (serialized) TargetInfo
-        // structure. The TargetInfo contains the measurement and attribute flags
-        // of the Quoting Enclave.
-        let qe_id: sgx_isa::Targetinfo = serde_json::from_reader(&mut stream)?;
-
-        // The enclave creates a Report attesting its identity, with the Quoting
-        // Enclave (whose identity was just received) as the Report's target. The
-        // blank ReportData field must be passed in as a &[u8; 64].
-        let report = {
-            let report_data = [0u8; 64];
-            Report::for_target(&qe_id, &report_data)
-        };
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let serve_ra_tls = args.iter().any(|arg| arg == "--ra-tls");
+    args.retain(|arg| arg != "--ra-tls");
+    let seal_policy = match take_option(&mut args, "--seal-policy")?.as_deref() {
+        None | Some("mrenclave") => Keypolicy::MRENCLAVE,
+        Some("mrsigner") => Keypolicy::MRSIGNER,
+        Some(policy) => return Err(format!("Unknown seal policy: {}", policy).into()),
+    };
+    let peer_addr = take_option(&mut args, "--attest-peer")?;
+    let config = config(&mut args)?;
+
+    let (report_provider, key_provider) = providers(args)?;
+
+    if let Some(peer_addr) = peer_addr {
+        let attestation = local_attestation::attest(
+            &mut Stream::connect(&peer_addr.parse()?, &config.timeouts)?,
+            &*report_provider,
+            &*key_provider,
+        )?;
+        println!(
+            "Locally attested the enclave on {}: {}",
+            peer_addr, attestation
+        );
+        return Ok(());
+    }
+
+    if serve_ra_tls {
+        let server_config =
+            ra_tls::server_config(&*report_provider, &config.daemon, &config.timeouts)?;
+        let listener = Listener::bind(&config.ra_tls_listen)?;
+        println!("\nServing RA-TLS on {}....", config.ra_tls_listen);
+        let timeouts = config.timeouts;
+        thread::spawn(move || ra_tls::serve(listener, server_config, &timeouts));
+    }
 
-        // The enclave sends its attestation Report back to the attestation daemon.
-        serde_json::to_writer(&mut stream, &report)?;
+    let listener = Listener::bind(&config.listen)?;
+    let pool = WorkerPool::new(config.workers)
+        .map_err(|e| format!("Could not start {} workers: {}", config.workers, e))?;
+    let enclave = Arc::new(Enclave {
+        report_provider,
+        key_provider,
+        seal_policy,
+        secrets: Mutex::new(SecretStore::new()),
+        timeouts: config.timeouts,
+    });
 
-        println!("Successfully sent report to daemon.");
+    println!(
+        "\nListening on {}, with {} workers....\n",
+        config.listen, config.workers
+    );
+
+    // The enclave handles each incoming connection from attestation daemon on a worker of
+    // its own. The daemon may request any number of Reports over its connection, and
+    // relays the secure channel between the enclave and the tenant that requested the
+    // latest one. Whatever goes wrong with one connection only ends that connection.
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                println!("Could not accept a connection: {}", e);
+                continue;
+            }
+        };
+        let enclave = Arc::clone(&enclave);
+        pool.execute(move || {
+            if let Err(e) = serve(&enclave, stream) {
+                println!("Connection closed: {}", e);
+            }
+        });
     }
 
     Ok(())
 }
+
+/// The state the connections share.
+struct Enclave {
+    report_provider: Box<dyn ReportProvider + Send + Sync>,
+    key_provider: Box<dyn KeyProvider + Send + Sync>,
+    seal_policy: Keypolicy,
+    /// The secrets provisioned by tenants over their secure channels, or unsealed.
+    secrets: Mutex<SecretStore>,
+    timeouts: Timeouts,
+}
+
+impl Enclave {
+    /// This takes the lock even if a connection panicked while holding it: the secret
+    /// store is not left half-updated by a panic, and a panic must not take the secrets
+    /// away from the other connections.
+    fn secrets(&self) -> MutexGuard<'_, SecretStore> {
+        self.secrets
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// This handles the requests of the daemon until it closes the connection. A request that
+/// cannot be served is answered with `Message::Error`, and the connection is only closed if
+/// it cannot be read or written any more.
+fn serve(enclave: &Enclave, mut stream: Stream) -> Result<(), protocol::Error> {
+    stream.set_timeouts(&enclave.timeouts)?;
+
+    // The ephemeral key bound into the latest Report, along with the tenant's nonce, until
+    // the tenant completes the key exchange; then the channel opened with it.
+    let mut key_exchange: Option<(KeyExchange, [u8; 32])> = None;
+    let mut channel: Option<SecureChannel> = None;
+
+    loop {
+        let message = match protocol::recv(&mut stream) {
+            Ok(Some(message)) => message,
+            Ok(None) => return Ok(()),
+
+            // The whole message was read, so the next one can still be: the daemon is told
+            // what was wrong with this one, and may carry on.
+            Err(e @ protocol::Error::Json(_)) => {
+                println!("Invalid request: {}", e);
+                protocol::send(
+                    &mut stream,
+                    &Message::Error {
+                        message: e.to_string(),
+                    },
+                )?;
+                continue;
+            }
+
+            // Where the next message starts is unknown: the daemon is told why, if it is
+            // still listening, and the connection is closed.
+            Err(e @ protocol::Error::Version(_)) | Err(e @ protocol::Error::TooLarge(_)) => {
+                let _ = protocol::send(
+                    &mut stream,
+                    &Message::Error {
+                        message: e.to_string(),
+                    },
+                );
+                return Err(e);
+            }
+            Err(e) => return Err(e),
+        };
+
+        let reply = match message {
+            // The enclave receives the identity of the Quoting Enclave from the
+            // attestation daemon, in the form of a TargetInfo structure. The TargetInfo
+            // contains the measurement and attribute flags of the Quoting Enclave. It also
+            // receives the tenant's nonce. Another enclave attesting this one locally sends
+            // its own TargetInfo and nonce instead.
+            Message::TargetInfo { target_info, nonce } => {
+                // The enclave creates a Report attesting its identity, with the Quoting
+                // Enclave (whose identity was just received) as the Report's target. The
+                // ReportData field binds the tenant's nonce and the public key of a new
+                // ephemeral key, with which the tenant can open a secure channel once it
+                // has verified the Quote.
+                let new_key_exchange = KeyExchange::new();
+                let enclave_data = new_key_exchange.public_key().to_vec();
+                let report_data = protocol::report_data(&nonce, &enclave_data);
+                match enclave.report_provider.report(&target_info, &report_data) {
+                    Ok(report) => {
+                        key_exchange = Some((new_key_exchange, nonce));
+                        channel = None;
+                        println!("Successfully sent report to daemon.");
+                        Message::Report {
+                            report,
+                            enclave_data,
+                        }
+                    }
+                    Err(e) => Message::Error {
+                        message: e.to_string(),
+                    },
+                }
+            }
+
+            // The tenant verified the Quote and sends its own ephemeral public key. The
+            // enclave derives the session keys, and confirms the channel with an empty
+            // record, which only the holder of the same keys can decrypt.
+            Message::KeyExchange { public_key } => match key_exchange.take() {
+                Some((key_exchange, nonce)) => {
+                    match open_channel(key_exchange, &public_key, &nonce) {
+                        Ok((new_channel, record)) => {
+                            channel = Some(new_channel);
+                            println!("Secure channel opened with the tenant.");
+                            Message::Encrypted { record }
+                        }
+                        Err(e) => Message::Error {
+                            message: e.to_string(),
+                        },
+                    }
+                }
+                None => Message::Error {
+                    message: "No attestation is awaiting a key exchange.".to_string(),
+                },
+            },
+
+            // The tenant provisions its secrets over the secure channel. The enclave stores
+            // each one and acknowledges it. It also seals the secret, and hands it to the
+            // daemon to store, ahead of its acknowledgement. A secret whose name was
+            // already provisioned is refused, so that it cannot replace the stored one.
+            Message::Encrypted { record } => match channel.as_mut() {
+                Some(channel) => {
+                    let mut secrets = enclave.secrets();
+                    match secrets.receive(channel, &record) {
+                        Ok(Receipt::Refused { name, record }) => {
+                            println!("Refused the secret {}: it was already provisioned.", name);
+                            Message::Encrypted { record }
+                        }
+                        Ok(Receipt::Stored { name, record }) => {
+                            println!("Secret provisioned by the tenant.");
+                            let secret = secrets.get(&name).expect("The secret was just stored.");
+                            let sealed = seal::seal(
+                                &*enclave.key_provider,
+                                enclave.seal_policy,
+                                secret,
+                                name.as_bytes(),
+                            );
+                            // The other connections need not wait for the daemon.
+                            drop(secrets);
+                            match sealed {
+                                Ok(blob) => {
+                                    protocol::send(&mut stream, &Message::Sealed { name, blob })?
+                                }
+                                Err(e) => println!("Could not seal the secret: {}", e),
+                            }
+                            Message::Encrypted { record }
+                        }
+                        Err(e) => Message::Error {
+                            message: e.to_string(),
+                        },
+                    }
+                }
+                None => Message::Error {
+                    message: "No secure channel is open.".to_string(),
+                },
+            },
+
+            // The daemon hands back the secrets the enclave sealed, e.g. before it was
+            // restarted. Those the enclave cannot unseal are skipped.
+            Message::Unseal { blobs } => Message::Unsealed {
+                names: unseal_secrets(&*enclave.key_provider, &mut enclave.secrets(), &blobs),
+            },
+
+            other => Message::Error {
+                message: protocol::Error::Unexpected(other.name()).to_string(),
+            },
+        };
+
+        // The enclave sends its reply, e.g. its attestation Report, back to the
+        // attestation daemon.
+        protocol::send(&mut stream, &reply)?;
+    }
+}
+
+/// This completes the key exchange with the tenant's public key, and returns the channel
+/// along with the record that confirms it to the tenant.
+fn open_channel(
+    key_exchange: KeyExchange,
+    tenant_public_key: &[u8; 32],
+    nonce: &[u8; 32],
+) -> Result<(SecureChannel, Vec<u8>), Box<dyn Error>> {
+    let mut channel = key_exchange.finish(Role::Enclave, tenant_public_key, nonce)?;
+    let record = channel.seal(&[])?;
+    Ok((channel, record))
+}
+
+/// This removes the option and its value from the arguments, and returns the value.
+fn take_option(args: &mut Vec<String>, option: &str) -> Result<Option<String>, Box<dyn Error>> {
+    match args.iter().position(|arg| arg == option) {
+        Some(i) if i + 1 < args.len() => Ok(Some(args.drain(i..i + 2).nth(1).unwrap())),
+        Some(_) => Err(format!("{} requires a value.", option).into()),
+        None => Ok(None),
+    }
+}
+
+/// This reads the configuration file given with `--config`, if any, and applies the options
+/// that override it.
+fn config(args: &mut Vec<String>) -> Result<Config, Box<dyn Error>> {
+    let mut config = match take_option(args, "--config")? {
+        Some(path) => Config::load(Path::new(&path))?,
+        None => Config::default(),
+    };
+    if let Some(listen) = take_option(args, "--listen")? {
+        config.listen = listen.parse()?;
+    }
+    if let Some(ra_tls_listen) = take_option(args, "--ra-tls-listen")? {
+        config.ra_tls_listen = ra_tls_listen.parse()?;
+    }
+    if let Some(daemon) = take_option(args, "--daemon")? {
+        config.daemon = daemon.parse()?;
+    }
+    if let Some(workers) = take_option(args, "--workers")? {
+        config.workers = workers.parse()?;
+    }
+    if config.workers == 0 {
+        return Err("The enclave needs at least one worker.".into());
+    }
+
+    let mut timeouts = Timeouts::default();
+    if let Some(connect) = take_option(args, "--connect-timeout")? {
+        timeouts.connect = Some(transport::parse_timeout(&connect)?);
+    }
+    if let Some(read) = take_option(args, "--read-timeout")? {
+        timeouts.read = Some(transport::parse_timeout(&read)?);
+    }
+    if let Some(write) = take_option(args, "--write-timeout")? {
+        timeouts.write = Some(transport::parse_timeout(&write)?);
+    }
+    config.timeouts = config.timeouts.overridden_by(timeouts);
+    Ok(config)
+}
+
+/// This unseals the blobs into the secret store, under the names they were sealed with, and
+/// returns those names.
+fn unseal_secrets(
+    key_provider: &dyn KeyProvider,
+    secrets: &mut SecretStore,
+    blobs: &[Vec<u8>],
+) -> Vec<String> {
+    let mut names = Vec::new();
+    for blob in blobs {
+        match seal::unseal(key_provider, blob) {
+            Ok(unsealed) => match String::from_utf8(unsealed.additional_data) {
+                Ok(name) => {
+                    if secrets.insert(name.clone(), unsealed.data) {
+                        names.push(name);
+                    } else {
+                        println!(
+                            "Could not unseal the secret {}: another was provisioned.",
+                            name
+                        );
+                    }
+                }
+                Err(_) => println!("Could not unseal a secret: its name is not UTF-8."),
+            },
+            Err(e) => println!("Could not unseal a secret: {}", e),
+        }
+    }
+    println!("Unsealed {} of {} secrets.", names.len(), blobs.len());
+    names
+}
+
+type Providers = (
+    Box<dyn ReportProvider + Send + Sync>,
+    Box<dyn KeyProvider + Send + Sync>,
+);
+
+/// Inside SGX, the CPU creates the enclave's Reports and SEAL keys, so no argument is left
+/// to choose its identity.
+#[cfg(target_env = "sgx")]
+fn providers(args: Vec<String>) -> Result<Providers, Box<dyn Error>> {
+    if let Some(arg) = args.first() {
+        return Err(format!("Unknown argument: {}", arg).into());
+    }
+    Ok((
+        Box::new(report_provider::SgxReportProvider),
+        Box::new(key_provider::SgxKeyProvider),
+    ))
+}
+
+/// Outside of SGX, the enclave's Reports and SEAL keys are simulated, and the identity they
+/// are bound to can be chosen with `--mrenclave HEX`, `--mrsigner HEX`, `--isvprodid N`,
+/// `--isvsvn N` and `--debug`. Only the simulated Quoting Enclave of the daemon
+/// (`--simulate`) accepts the Reports.
+#[cfg(not(target_env = "sgx"))]
+fn providers(args: Vec<String>) -> Result<Providers, Box<dyn Error>> {
+    use attestation_sim::EnclaveIdentity;
+    use sgx_isa::AttributesFlags;
+
+    let mut identity = EnclaveIdentity::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} requires a value.", arg));
+        match &arg[..] {
+            "--mrenclave" => identity.mrenclave = measurement(&value()?)?,
+            "--mrsigner" => identity.mrsigner = measurement(&value()?)?,
+            "--isvprodid" => identity.isvprodid = value()?.parse()?,
+            "--isvsvn" => identity.isvsvn = value()?.parse()?,
+            "--debug" => identity.attributes.flags |= AttributesFlags::DEBUG,
+            _ => return Err(format!("Unknown argument: {}", arg).into()),
+        }
+    }
+
+    println!(
+        "Simulating the enclave, with MRENCLAVE {} and MRSIGNER {}.",
+        hex::encode(identity.mrenclave),
+        hex::encode(identity.mrsigner)
+    );
+    Ok((
+        Box::new(report_provider::SimReportProvider(identity.clone())),
+        Box::new(key_provider::SimKeyProvider(identity)),
+    ))
+}
+
+#[cfg(not(target_env = "sgx"))]
+fn measurement(value: &str) -> Result<[u8; 32], Box<dyn Error>> {
+    let bytes = hex::decode(value)?;
+    if bytes.len() != 32 {
+        return Err(format!("{} is not a 32-byte measurement.", value).into());
+    }
+    let mut measurement = [0u8; 32];
+    measurement.copy_from_slice(&bytes);
+    Ok(measurement)
+}
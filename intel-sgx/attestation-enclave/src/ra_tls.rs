@@ -0,0 +1,80 @@
+use crate::report_provider::ReportProvider;
+use attestation_protocol::transport::{Address, Listener, Stream, Timeouts};
+use attestation_protocol::{self as protocol, ra_tls, Message};
+use rcgen::{Certificate, CertificateParams, CustomExtension, KeyPair, PKCS_ECDSA_P256_SHA256};
+use rustls::{ServerConfig, ServerConnection, StreamOwned};
+use std::error::Error;
+use std::io::{Read, Write};
+use std::sync::Arc;
+
+/// This generates the enclave's TLS key and has the attestation daemon quote a Report that
+/// binds it. It returns the TLS server configuration with the key's self-signed RA-TLS
+/// certificate, which carries the Quote.
+pub fn server_config(
+    report_provider: &dyn ReportProvider,
+    daemon_addr: &Address,
+    timeouts: &Timeouts,
+) -> Result<Arc<ServerConfig>, Box<dyn Error>> {
+    let key_pair = KeyPair::generate(&PKCS_ECDSA_P256_SHA256)?;
+    let report_data = ra_tls::report_data(&key_pair.public_key_der());
+
+    // The enclave asks the daemon for the Quoting Enclave's Target Info, creates a Report
+    // targeted at it which binds the key, and has the daemon quote it.
+    let mut daemon_stream = Stream::connect(daemon_addr, timeouts)?;
+    protocol::send(&mut daemon_stream, &Message::QeTargetInfoRequest)?;
+    let target_info = match protocol::recv_reply(&mut daemon_stream)? {
+        Message::QeTargetInfo { target_info } => target_info,
+        other => return Err(protocol::Error::Unexpected(other.name()).into()),
+    };
+    let report = report_provider.report(&target_info, &report_data)?;
+    protocol::send(&mut daemon_stream, &Message::QuoteRequest { report })?;
+    let quote = match protocol::recv_reply(&mut daemon_stream)? {
+        Message::Quote { quote, .. } => quote,
+        other => return Err(protocol::Error::Unexpected(other.name()).into()),
+    };
+
+    // The certificate is self-signed: it is trusted because of the Quote, not its issuer.
+    let mut params = CertificateParams::new(vec!["localhost".to_string()]);
+    params.alg = &PKCS_ECDSA_P256_SHA256;
+    params.key_pair = Some(key_pair);
+    params.custom_extensions = vec![CustomExtension::from_oid_content(
+        ra_tls::QUOTE_EXTENSION_OID,
+        quote,
+    )];
+    let certificate = Certificate::from_params(params)?;
+
+    let config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![rustls::Certificate(certificate.serialize_der()?)],
+            rustls::PrivateKey(certificate.serialize_private_key_der()),
+        )?;
+    Ok(Arc::new(config))
+}
+
+/// This serves RA-TLS connections one at a time. The server is a demonstration of the
+/// attested TLS session: it echoes whatever it receives.
+pub fn serve(listener: Listener, config: Arc<ServerConfig>, timeouts: &Timeouts) {
+    for stream in listener.incoming() {
+        let result = stream
+            .and_then(|stream| stream.set_timeouts(timeouts).map(|()| stream))
+            .map_err(|e| e.into())
+            .and_then(|stream| echo(stream, config.clone()));
+        if let Err(e) = result {
+            println!("RA-TLS connection failed: {}", e);
+        }
+    }
+}
+
+fn echo(stream: Stream, config: Arc<ServerConfig>) -> Result<(), Box<dyn Error>> {
+    let mut tls = StreamOwned::new(ServerConnection::new(config)?, stream);
+    let mut buf = [0u8; 4096];
+    loop {
+        let len = tls.read(&mut buf)?;
+        if len == 0 {
+            return Ok(());
+        }
+        tls.write_all(&buf[..len])?;
+    }
+}
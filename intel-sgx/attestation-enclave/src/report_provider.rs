@@ -0,0 +1,57 @@
+use sgx_isa::{Report, Targetinfo};
+use std::error::Error;
+
+/// The source of the enclave's attestation Reports.
+pub trait ReportProvider {
+    /// This creates a Report of the enclave, targeted at the given enclave (the Quoting
+    /// Enclave) and carrying report_data.
+    fn report(
+        &self,
+        target_info: &Targetinfo,
+        report_data: &[u8; 64],
+    ) -> Result<Report, Box<dyn Error>>;
+
+    /// The enclave's own Target Info, at which other enclaves target the Reports they send
+    /// it for local attestation.
+    fn target_info(&self) -> Result<Targetinfo, Box<dyn Error>>;
+}
+
+/// Reports created by the CPU with ENCLU[EREPORT], which only works inside an SGX enclave.
+#[cfg(target_env = "sgx")]
+pub struct SgxReportProvider;
+
+#[cfg(target_env = "sgx")]
+impl ReportProvider for SgxReportProvider {
+    fn report(
+        &self,
+        target_info: &Targetinfo,
+        report_data: &[u8; 64],
+    ) -> Result<Report, Box<dyn Error>> {
+        Ok(Report::for_target(target_info, report_data))
+    }
+
+    fn target_info(&self) -> Result<Targetinfo, Box<dyn Error>> {
+        Ok(Targetinfo::from(Report::for_self()))
+    }
+}
+
+/// Reports of a simulated enclave, whose identity is chosen by the caller, MACed as the
+/// simulated Quoting Enclave of attestation-sim, and other simulated enclaves, expect. They
+/// prove nothing.
+#[cfg(not(target_env = "sgx"))]
+pub struct SimReportProvider(pub attestation_sim::EnclaveIdentity);
+
+#[cfg(not(target_env = "sgx"))]
+impl ReportProvider for SimReportProvider {
+    fn report(
+        &self,
+        target_info: &Targetinfo,
+        report_data: &[u8; 64],
+    ) -> Result<Report, Box<dyn Error>> {
+        self.0.report(target_info, report_data)
+    }
+
+    fn target_info(&self) -> Result<Targetinfo, Box<dyn Error>> {
+        Ok(self.0.target_info())
+    }
+}
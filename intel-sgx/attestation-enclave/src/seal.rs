@@ -0,0 +1,232 @@
+//! Sealing: data encrypted under the enclave's SEAL key, which only the same enclave (with
+//! the MRENCLAVE key policy) or an enclave of the same signer (with the MRSIGNER key policy)
+//! can derive on the same platform. Sealed data can be stored outside of the enclave, and
+//! unsealed after the enclave restarts.
+//!
+//! A sealed blob is made up of the fields of the key request, which are needed to derive
+//! the same key again, followed by the AES-128-GCM encryption of the data:
+//!
+//! | Bytes      | Field                                                 |
+//! |------------|-------------------------------------------------------|
+//! | 0..2       | format version (`VERSION`), little endian             |
+//! | 2..4       | KEYPOLICY, little endian                              |
+//! | 4..6       | ISVSVN the key was derived for, little endian         |
+//! | 6..22      | CPUSVN the key was derived for                        |
+//! | 22..54     | KEYID, random                                         |
+//! | 54..66     | AES-GCM nonce, random                                 |
+//! | 66..70     | length n of the additional data, little endian        |
+//! | 70..70+n   | additional data, in the clear                         |
+//! | 70+n..     | encrypted data, followed by the 16-byte GCM tag       |
+//!
+//! Everything before the encrypted data is authenticated as GCM additional data, so the
+//! blob cannot be altered, nor its additional data replaced, without unsealing failing.
+//! Blobs are sealed for the enclave's current ISVSVN and CPUSVN, so that a later version of
+//! the enclave, or the same enclave on an updated platform, can still unseal them.
+
+use crate::key_provider::KeyProvider;
+use aes_gcm::aead::{generic_array::GenericArray, Aead, NewAead, Payload};
+use aes_gcm::Aes128Gcm;
+use rand_core::{OsRng, RngCore};
+use sgx_isa::{Keyname, Keypolicy, Keyrequest};
+use std::convert::TryInto;
+use std::error::Error;
+
+/// The version of the sealed-blob format.
+const VERSION: u16 = 1;
+
+const HEADER_LEN: usize = 70;
+
+/// The SEAL key is bound to all attribute flags but MODE64BIT, PROVISIONKEY, EINITTOKENKEY
+/// and bits 6 and 7, as Intel's SDK does: in particular, a debug enclave cannot unseal the
+/// data of a production enclave. It is not bound to XFRM.
+const ATTRIBUTE_MASK: [u64; 2] = [0xffff_ffff_ffff_ff0b, 0];
+
+/// The SEAL key is bound to the reserved MISCSELECT bits only, as Intel's SDK does.
+const MISC_MASK: u32 = 0xf000_0000;
+
+/// The data and additional data of an unsealed blob.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Unsealed {
+    pub data: Vec<u8>,
+    pub additional_data: Vec<u8>,
+}
+
+/// This seals the data with a new key of the given policy. The additional data is stored in
+/// the clear, but authenticated.
+pub fn seal(
+    key_provider: &dyn KeyProvider,
+    policy: Keypolicy,
+    data: &[u8],
+    additional_data: &[u8],
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let (isvsvn, cpusvn) = key_provider.svn()?;
+    let mut keyid = [0u8; 32];
+    OsRng.fill_bytes(&mut keyid);
+    let mut nonce = [0u8; 12];
+    OsRng.fill_bytes(&mut nonce);
+
+    let mut blob = Vec::with_capacity(HEADER_LEN + additional_data.len() + data.len() + 16);
+    blob.extend(&VERSION.to_le_bytes());
+    blob.extend(&policy.bits().to_le_bytes());
+    blob.extend(&isvsvn.to_le_bytes());
+    blob.extend(&cpusvn);
+    blob.extend(&keyid);
+    blob.extend(&nonce);
+    blob.extend(&(additional_data.len() as u32).to_le_bytes());
+    blob.extend(additional_data);
+
+    let key = key_provider.key(&key_request(policy, isvsvn, cpusvn, keyid))?;
+    let ciphertext = Aes128Gcm::new(GenericArray::from_slice(&key))
+        .encrypt(
+            GenericArray::from_slice(&nonce),
+            Payload {
+                msg: data,
+                aad: &blob,
+            },
+        )
+        .map_err(|_| "The data could not be sealed.")?;
+    blob.extend(ciphertext);
+    Ok(blob)
+}
+
+/// This unseals a blob sealed by this enclave or, with the MRSIGNER policy, by an enclave of
+/// the same signer. It fails if the blob was sealed by another enclave, on another platform,
+/// or for a later ISVSVN or CPUSVN, or if it was altered.
+pub fn unseal(key_provider: &dyn KeyProvider, blob: &[u8]) -> Result<Unsealed, Box<dyn Error>> {
+    if blob.len() < HEADER_LEN {
+        return Err("The sealed blob is truncated.".into());
+    }
+    let version = u16::from_le_bytes([blob[0], blob[1]]);
+    if version != VERSION {
+        return Err(format!("Unsupported sealed blob version {}.", version).into());
+    }
+    let policy = Keypolicy::from_bits(u16::from_le_bytes([blob[2], blob[3]]))
+        .ok_or("The sealed blob has an invalid key policy.")?;
+    let isvsvn = u16::from_le_bytes([blob[4], blob[5]]);
+    let cpusvn: [u8; 16] = blob[6..22].try_into()?;
+    let keyid: [u8; 32] = blob[22..54].try_into()?;
+    let nonce = &blob[54..66];
+    let additional_data_len = u32::from_le_bytes(blob[66..70].try_into()?) as usize;
+    if blob.len() - HEADER_LEN < additional_data_len {
+        return Err("The sealed blob is truncated.".into());
+    }
+    let (header, ciphertext) = blob.split_at(HEADER_LEN + additional_data_len);
+
+    let key = key_provider.key(&key_request(policy, isvsvn, cpusvn, keyid))?;
+    let data = Aes128Gcm::new(GenericArray::from_slice(&key))
+        .decrypt(
+            GenericArray::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: header,
+            },
+        )
+        .map_err(|_| "The sealed blob was altered, or sealed by another enclave.")?;
+
+    Ok(Unsealed {
+        data,
+        additional_data: header[HEADER_LEN..].to_vec(),
+    })
+}
+
+fn key_request(policy: Keypolicy, isvsvn: u16, cpusvn: [u8; 16], keyid: [u8; 32]) -> Keyrequest {
+    Keyrequest {
+        keyname: Keyname::Seal as u16,
+        keypolicy: policy,
+        isvsvn,
+        cpusvn,
+        attributemask: ATTRIBUTE_MASK,
+        keyid,
+        miscmask: MISC_MASK,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key_provider::SimKeyProvider;
+    use attestation_sim::EnclaveIdentity;
+
+    fn enclave() -> SimKeyProvider {
+        SimKeyProvider(EnclaveIdentity {
+            isvsvn: 2,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn sealed_data_unseals() {
+        for &policy in &[Keypolicy::MRENCLAVE, Keypolicy::MRSIGNER] {
+            let blob = seal(&enclave(), policy, b"disk-key material", b"disk-key").unwrap();
+            assert_eq!(
+                unseal(&enclave(), &blob).unwrap(),
+                Unsealed {
+                    data: b"disk-key material".to_vec(),
+                    additional_data: b"disk-key".to_vec(),
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn mrenclave_policy_is_bound_to_enclave() {
+        let blob = seal(&enclave(), Keypolicy::MRENCLAVE, b"secret", b"").unwrap();
+
+        let mut other = enclave();
+        other.0.mrenclave = [0x1d; 32];
+        assert!(unseal(&other, &blob).is_err());
+    }
+
+    #[test]
+    fn mrsigner_policy_is_bound_to_signer() {
+        let blob = seal(&enclave(), Keypolicy::MRSIGNER, b"secret", b"").unwrap();
+
+        // A later version of the enclave, by the same signer, can unseal the data...
+        let mut upgraded = enclave();
+        upgraded.0.mrenclave = [0x1d; 32];
+        upgraded.0.isvsvn = 3;
+        assert_eq!(unseal(&upgraded, &blob).unwrap().data, b"secret");
+
+        // ...but not an enclave of another signer, nor of another product.
+        let mut other_signer = enclave();
+        other_signer.0.mrsigner = [0x5a; 32];
+        assert!(unseal(&other_signer, &blob).is_err());
+        let mut other_product = enclave();
+        other_product.0.isvprodid = 1;
+        assert!(unseal(&other_product, &blob).is_err());
+    }
+
+    #[test]
+    fn earlier_version_cannot_unseal() {
+        let blob = seal(&enclave(), Keypolicy::MRSIGNER, b"secret", b"").unwrap();
+
+        let mut downgraded = enclave();
+        downgraded.0.isvsvn = 1;
+        assert!(unseal(&downgraded, &blob).is_err());
+    }
+
+    #[test]
+    fn debug_enclave_cannot_unseal() {
+        let blob = seal(&enclave(), Keypolicy::MRENCLAVE, b"secret", b"").unwrap();
+
+        let mut debug = enclave();
+        debug.0.attributes.flags |= sgx_isa::AttributesFlags::DEBUG;
+        assert!(unseal(&debug, &blob).is_err());
+    }
+
+    #[test]
+    fn altered_blob_is_rejected() {
+        let blob = seal(&enclave(), Keypolicy::MRENCLAVE, b"secret", b"disk-key").unwrap();
+
+        // Every byte is either authenticated or needed to derive the key, so altering any of
+        // them must make unsealing fail.
+        for i in 0..blob.len() {
+            let mut altered = blob.clone();
+            altered[i] ^= 0x01;
+            assert!(unseal(&enclave(), &altered).is_err(), "byte {}", i);
+        }
+        assert!(unseal(&enclave(), &blob[..blob.len() - 1]).is_err());
+        assert!(unseal(&enclave(), &blob[..HEADER_LEN - 1]).is_err());
+    }
+}
@@ -0,0 +1,116 @@
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// A fixed number of worker threads, which run the jobs handed to the pool. Handing a job
+/// to the pool blocks until a worker is free, so that the enclave stops accepting
+/// connections while every worker is busy, rather than spawning threads without bound; the
+/// pending connections wait in the listener's backlog. Inside SGX, the enclave can only run
+/// as many threads as it was built with (see its `Cargo.toml`).
+///
+/// A job that panics only ends itself: its worker carries on with the next job.
+pub struct WorkerPool {
+    sender: Option<SyncSender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl WorkerPool {
+    /// This starts the given number of workers, which must not be zero. It fails if the
+    /// workers cannot all be started, e.g. when the enclave has fewer threads than that.
+    pub fn new(size: usize) -> io::Result<Self> {
+        assert!(size > 0, "A worker pool needs at least one worker.");
+
+        // With a queue of size zero, a job is only handed over once a worker takes it.
+        let (sender, receiver) = mpsc::sync_channel(0);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let mut pool = WorkerPool {
+            sender: Some(sender),
+            workers: Vec::with_capacity(size),
+        };
+        for _ in 0..size {
+            let receiver = Arc::clone(&receiver);
+            // If a worker cannot be started, dropping the pool stops those that were.
+            pool.workers
+                .push(thread::Builder::new().spawn(move || work(&receiver))?);
+        }
+        Ok(pool)
+    }
+
+    /// This runs the job on the next free worker, waiting for one if they are all busy.
+    pub fn execute<F: FnOnce() + Send + 'static>(&self, job: F) {
+        self.sender
+            .as_ref()
+            .expect("The pool is only closed when dropped.")
+            .send(Box::new(job))
+            .expect("Workers only stop once the pool is closed.");
+    }
+}
+
+/// This runs jobs until the pool is closed.
+fn work(receiver: &Mutex<Receiver<Job>>) {
+    loop {
+        // The lock is released as soon as a job is taken, so that the other workers can
+        // take the following ones. It cannot be poisoned, as jobs run without it.
+        let job = match receiver.lock().expect("No job runs under the lock.").recv() {
+            Ok(job) => job,
+            Err(_) => return,
+        };
+        if panic::catch_unwind(AssertUnwindSafe(job)).is_err() {
+            println!("\nA worker recovered from a panic; its connection was dropped.");
+        }
+    }
+}
+
+impl Drop for WorkerPool {
+    /// This waits for the jobs in progress to finish.
+    fn drop(&mut self) {
+        drop(self.sender.take());
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Barrier;
+
+    #[test]
+    fn jobs_run_concurrently() {
+        // Every job waits for all the others, so they only finish if they run at once.
+        let pool = WorkerPool::new(4).unwrap();
+        let barrier = Arc::new(Barrier::new(4));
+        let done = Arc::new(AtomicUsize::new(0));
+        for _ in 0..4 {
+            let barrier = Arc::clone(&barrier);
+            let done = Arc::clone(&done);
+            pool.execute(move || {
+                barrier.wait();
+                done.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+        drop(pool);
+        assert_eq!(done.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn panicking_job_does_not_stop_its_worker() {
+        let pool = WorkerPool::new(1).unwrap();
+        let done = Arc::new(AtomicUsize::new(0));
+        pool.execute(|| panic!("bad message"));
+        for _ in 0..3 {
+            let done = Arc::clone(&done);
+            pool.execute(move || {
+                done.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+        drop(pool);
+        assert_eq!(done.load(Ordering::SeqCst), 3);
+    }
+}
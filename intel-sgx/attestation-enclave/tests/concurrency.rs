@@ -0,0 +1,153 @@
+//! Concurrent tenants, against the enclave binary running with simulated Reports: the
+//! enclave serves each daemon connection on its own, so that a tenant holding its session
+//! open does not keep the other tenants from attesting the enclave.
+
+use attestation_protocol::{self as protocol, Message};
+use attestation_sim::{EnclaveIdentity, Platform, QuotingEnclave};
+use attestation_tenant::{Attestation, Collateral, Policy};
+use std::env;
+use std::fs;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::process::{self, Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long the test waits on the enclave, before deciding it is not serving a connection.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The enclave, listening on a Unix domain socket until the test ends.
+struct Enclave {
+    process: Child,
+    dir: PathBuf,
+}
+
+impl Enclave {
+    fn start() -> Self {
+        let dir = env::temp_dir().join(format!("attestation-enclave-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let process = Command::new(env!("CARGO_BIN_EXE_attestation-enclave"))
+            .arg("--listen")
+            .arg(format!("unix:{}", dir.join("enclave.sock").display()))
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap();
+        Enclave { process, dir }
+    }
+
+    /// A connection to the enclave, as the daemon opens one for each tenant.
+    fn connect(&self) -> UnixStream {
+        let start = Instant::now();
+        let stream = loop {
+            match UnixStream::connect(self.dir.join("enclave.sock")) {
+                Ok(stream) => break stream,
+                Err(_) if start.elapsed() < TIMEOUT => thread::sleep(Duration::from_millis(50)),
+                Err(e) => panic!("The enclave is not listening: {}", e),
+            }
+        };
+        stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+        stream.set_write_timeout(Some(TIMEOUT)).unwrap();
+        stream
+    }
+
+    /// This has a tenant attest the enclave through a daemon of its own, and returns the
+    /// tenant's verified attestation.
+    fn attest(&self) -> Attestation<UnixStream> {
+        let qe = QuotingEnclave::new(Platform::default()).unwrap();
+        let collateral = collateral(&qe);
+        let policy = Policy::new(vec![EnclaveIdentity::default().mrenclave], Vec::new()).unwrap();
+
+        let (tenant_stream, daemon_stream) = UnixStream::pair().unwrap();
+        let enclave_stream = self.connect();
+        thread::spawn(move || daemon(qe, daemon_stream, enclave_stream));
+
+        let mut attestation = Attestation::request(tenant_stream).unwrap();
+        assert!(attestation.verify(&collateral, &policy).unwrap().verified());
+        attestation
+    }
+}
+
+impl Drop for Enclave {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// The daemon's part in a tenant's session, reduced to what the test needs: it has the
+/// enclave's Reports quoted by the simulated Quoting Enclave, and relays the secure channel,
+/// until the tenant closes its connection.
+fn daemon(qe: QuotingEnclave, mut tenant_stream: UnixStream, mut enclave_stream: UnixStream) {
+    while let Some(message) = protocol::recv(&mut tenant_stream).unwrap() {
+        let reply = match message {
+            Message::AttestationRequest { nonce } => {
+                let target_info = qe.target_info();
+                protocol::send(
+                    &mut enclave_stream,
+                    &Message::TargetInfo { target_info, nonce },
+                )
+                .unwrap();
+                match protocol::recv_reply(&mut enclave_stream).unwrap() {
+                    Message::Report {
+                        report,
+                        enclave_data,
+                    } => Message::Quote {
+                        quote: qe.quote(&report).unwrap(),
+                        enclave_data,
+                    },
+                    other => panic!("unexpected {} message", other.name()),
+                }
+            }
+            message => {
+                protocol::send(&mut enclave_stream, &message).unwrap();
+                // The sealed secrets are for the daemon to store, not for the tenant.
+                loop {
+                    match protocol::recv(&mut enclave_stream).unwrap() {
+                        Some(Message::Sealed { .. }) => continue,
+                        Some(reply) => break reply,
+                        None => panic!("The enclave closed the connection."),
+                    }
+                }
+            }
+        };
+        protocol::send(&mut tenant_stream, &reply).unwrap();
+    }
+}
+
+/// The tenant's collateral for the simulated Quoting Enclave.
+fn collateral(qe: &QuotingEnclave) -> Collateral {
+    let pki = qe.pki();
+
+    let mut collateral = Collateral::from_pem(&pki.pck_chain_pem().unwrap()).unwrap();
+    collateral
+        .add_crl(&pki.root.issue_crl(&[], 30).unwrap())
+        .unwrap();
+    collateral
+        .add_crl(&pki.pck_ca.issue_crl(&[], 30).unwrap())
+        .unwrap();
+    let tcb_chain = pki.tcb_chain_pem().unwrap();
+    collateral
+        .set_tcb_info(&qe.tcb_info().unwrap(), &tcb_chain)
+        .unwrap();
+    collateral
+        .set_qe_identity(&qe.qe_identity().unwrap(), &tcb_chain)
+        .unwrap();
+    collateral
+}
+
+#[test]
+fn open_session_does_not_hold_up_other_tenants() {
+    let enclave = Enclave::start();
+
+    // The first tenant attests the enclave, opens the secure channel, and keeps it open.
+    let mut first = enclave.attest().open_channel().unwrap();
+
+    // Meanwhile, a second tenant attests the enclave and provisions its secret.
+    let mut second = enclave.attest().open_channel().unwrap();
+    second.provision("disk-key", &[0x5e; 32]).unwrap();
+    drop(second);
+
+    // The first tenant's session is still served.
+    first.provision("config", b"{\"debug\": false}").unwrap();
+}
@@ -0,0 +1,184 @@
+//! The secure channel between the tenant and the attested enclave.
+//!
+//! The enclave generates an ephemeral X25519 key for every attestation, and sends its
+//! public key as its enclave data, which the Report (and so the Quote) binds together with
+//! the tenant's nonce. Once the tenant has verified the Quote, it knows that the key belongs
+//! to the attested enclave: it generates its own ephemeral key, sends its public key in
+//! `Message::KeyExchange`, and both sides derive the session keys from the shared secret
+//! with HKDF-SHA256, salted with the nonce.
+//!
+//! Each direction has its own AES-256-GCM key. Records are not numbered on the wire: the
+//! nonce of a record is its sequence number in its direction, so a record that is dropped,
+//! replayed or reordered fails to decrypt, as does a forged one.
+
+use aes_gcm::aead::{generic_array::GenericArray, Aead, NewAead};
+use aes_gcm::Aes256Gcm;
+use hkdf::Hkdf;
+use rand_core::OsRng;
+use sha2::Sha256;
+use std::error::Error as StdError;
+use std::fmt::{Display, Formatter};
+use x25519_dalek::{EphemeralSecret, PublicKey};
+
+/// The HKDF info of the session keys, followed by the tenant's and the enclave's public keys.
+const KEY_LABEL: &[u8] = b"attestation-protocol secure channel v1";
+
+const KEY_LEN: usize = 32;
+
+/// The side of the channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Tenant,
+    Enclave,
+}
+
+/// This is the error returned when the channel cannot be established or a record cannot be
+/// sent or received.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChannelError {
+    /// The peer's public key is a low-order point, which would make the shared secret
+    /// predictable.
+    WeakKey,
+
+    /// The record was forged, replayed, reordered or encrypted under another key.
+    Forged,
+
+    /// The channel has sent or received as many records as its nonces allow.
+    Exhausted,
+}
+
+impl StdError for ChannelError {}
+
+impl Display for ChannelError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            ChannelError::WeakKey => write!(f, "peer's public key is not a valid X25519 key"),
+            ChannelError::Forged => write!(f, "record could not be authenticated"),
+            ChannelError::Exhausted => write!(f, "channel has run out of record numbers"),
+        }
+    }
+}
+
+/// One side's ephemeral key, before the peer's public key is known.
+pub struct KeyExchange {
+    secret: EphemeralSecret,
+    public_key: [u8; 32],
+}
+
+impl KeyExchange {
+    /// This generates a new ephemeral key. It must be used for a single channel only.
+    pub fn new() -> Self {
+        let secret = EphemeralSecret::new(OsRng);
+        let public_key = PublicKey::from(&secret).to_bytes();
+        KeyExchange { secret, public_key }
+    }
+
+    /// The public key, which is sent to the peer.
+    pub fn public_key(&self) -> [u8; 32] {
+        self.public_key
+    }
+
+    /// This completes the key exchange with the peer's public key, and derives the session
+    /// keys. The nonce is the tenant's nonce of the attestation the enclave's key is bound to.
+    pub fn finish(
+        self,
+        role: Role,
+        peer_public_key: &[u8; 32],
+        nonce: &[u8; 32],
+    ) -> Result<SecureChannel, ChannelError> {
+        let shared_secret = self
+            .secret
+            .diffie_hellman(&PublicKey::from(*peer_public_key));
+        if shared_secret.as_bytes().iter().all(|&b| b == 0) {
+            return Err(ChannelError::WeakKey);
+        }
+
+        let (tenant_key, enclave_key) = match role {
+            Role::Tenant => (&self.public_key, peer_public_key),
+            Role::Enclave => (peer_public_key, &self.public_key),
+        };
+        let mut info = KEY_LABEL.to_vec();
+        info.extend(&tenant_key[..]);
+        info.extend(&enclave_key[..]);
+
+        // The first key encrypts the tenant's records, the second the enclave's.
+        let mut keys = [0u8; 2 * KEY_LEN];
+        Hkdf::<Sha256>::new(Some(&nonce[..]), shared_secret.as_bytes())
+            .expand(&info, &mut keys)
+            .expect("HKDF-SHA256 can expand 64 bytes.");
+        let (tenant_key, enclave_key) = keys.split_at(KEY_LEN);
+        let (send_key, recv_key) = match role {
+            Role::Tenant => (tenant_key, enclave_key),
+            Role::Enclave => (enclave_key, tenant_key),
+        };
+
+        Ok(SecureChannel {
+            send: Direction::new(send_key),
+            recv: Direction::new(recv_key),
+        })
+    }
+}
+
+impl Default for KeyExchange {
+    fn default() -> Self {
+        KeyExchange::new()
+    }
+}
+
+/// An established channel, which encrypts the records sent to the peer and decrypts those
+/// received from it.
+pub struct SecureChannel {
+    send: Direction,
+    recv: Direction,
+}
+
+impl SecureChannel {
+    /// This encrypts the next record sent to the peer.
+    pub fn seal(&mut self, plaintext: &[u8]) -> Result<Vec<u8>, ChannelError> {
+        let record = self
+            .send
+            .cipher
+            .encrypt(GenericArray::from_slice(&self.send.nonce()?), plaintext)
+            .map_err(|_| ChannelError::Exhausted)?;
+        self.send.sequence += 1;
+        Ok(record)
+    }
+
+    /// This decrypts the next record received from the peer. A record that fails to
+    /// decrypt does not count, so the channel still expects the genuine one.
+    pub fn open(&mut self, record: &[u8]) -> Result<Vec<u8>, ChannelError> {
+        let plaintext = self
+            .recv
+            .cipher
+            .decrypt(GenericArray::from_slice(&self.recv.nonce()?), record)
+            .map_err(|_| ChannelError::Forged)?;
+        self.recv.sequence += 1;
+        Ok(plaintext)
+    }
+}
+
+/// The key and record count of one direction of the channel.
+struct Direction {
+    cipher: Aes256Gcm,
+    sequence: u64,
+}
+
+impl Direction {
+    fn new(key: &[u8]) -> Self {
+        Direction {
+            cipher: Aes256Gcm::new(GenericArray::from_slice(key)),
+            sequence: 0,
+        }
+    }
+
+    /// The 96-bit nonce of the next record is its big endian sequence number. The last
+    /// sequence number is never used, so that it cannot wrap around.
+    fn nonce(&self) -> Result<[u8; 12], ChannelError> {
+        if self.sequence == u64::MAX {
+            return Err(ChannelError::Exhausted);
+        }
+        let mut nonce = [0u8; 12];
+        nonce[4..].copy_from_slice(&self.sequence.to_be_bytes());
+        Ok(nonce)
+    }
+}
@@ -0,0 +1,373 @@
+//! The protocol spoken between the attestation tenant, daemon and enclave.
+//!
+//! Every message is sent as a frame made up of a 6-byte header and a payload:
+//!
+//! | Bytes | Field                                     |
+//! |-------|-------------------------------------------|
+//! | 0..2  | protocol version, big endian (`VERSION`)  |
+//! | 2..6  | payload length, big endian                |
+//! | 6..   | the JSON-encoded `Message`                |
+//!
+//! Because every message is delimited, a single connection can carry any number of
+//! exchanges, and a failure can be reported with `Message::Error` instead of looking like
+//! a truncated stream.
+
+pub mod channel;
+pub mod oid;
+pub mod provisioning;
+pub mod ra_tls;
+pub mod transport;
+
+use serde::{Deserialize, Serialize};
+use sgx_isa::{Report, Targetinfo};
+use sha2::{Digest, Sha256};
+use std::error::Error as StdError;
+use std::fmt::{Display, Formatter};
+use std::io::{self, Read, Write};
+
+/// The version of the protocol implemented by this crate.
+pub const VERSION: u16 = 1;
+
+/// Frames with larger payloads are rejected rather than buffered.
+pub const MAX_PAYLOAD_LEN: u32 = 1 << 20;
+
+const HEADER_LEN: usize = 6;
+
+/// A protocol message.
+#[derive(Serialize, Deserialize)]
+pub enum Message {
+    /// Tenant to daemon: requests a Quote for the enclave, bound to a fresh nonce.
+    AttestationRequest { nonce: [u8; 32] },
+
+    /// Daemon to enclave: the Quoting Enclave's Target Info and the tenant's nonce.
+    TargetInfo {
+        target_info: Targetinfo,
+        nonce: [u8; 32],
+    },
+
+    /// Enclave to daemon: a Report targeting the Quoting Enclave, along with any data
+    /// of the enclave's own that is bound into its ReportData.
+    Report {
+        report: Report,
+        enclave_data: Vec<u8>,
+    },
+
+    /// Daemon to tenant: the Quote for the enclave's Report, and the enclave's data.
+    Quote {
+        quote: Vec<u8>,
+        enclave_data: Vec<u8>,
+    },
+
+    /// Tenant to enclave, relayed by the daemon: the tenant's ephemeral public key, which
+    /// opens a secure channel with the enclave it just attested. The enclave confirms the
+    /// channel with an `Encrypted` record of its own.
+    KeyExchange { public_key: [u8; 32] },
+
+    /// Tenant to enclave or enclave to tenant, relayed by the daemon: a record of the
+    /// secure channel.
+    Encrypted { record: Vec<u8> },
+
+    /// Enclave to daemon: requests the Quoting Enclave's Target Info, so that the enclave
+    /// can have a Report of its own quoted, e.g. for its RA-TLS certificate.
+    QeTargetInfoRequest,
+
+    /// Daemon to enclave: the Quoting Enclave's Target Info.
+    QeTargetInfo { target_info: Targetinfo },
+
+    /// Enclave to daemon: requests a Quote for the Report, which is answered with `Quote`.
+    QuoteRequest { report: Report },
+
+    /// Enclave to daemon: a provisioned secret, sealed by the enclave so that it survives
+    /// restarts. It is sent just before the enclave's reply to the record that provisioned
+    /// the secret, and is not answered. The daemon stores it for the enclave, replacing any
+    /// earlier blob of the same name.
+    Sealed { name: String, blob: Vec<u8> },
+
+    /// Daemon to enclave: the sealed secrets stored for the enclave, sent when the daemon
+    /// connects to it. The enclave answers with `Unsealed`.
+    Unseal { blobs: Vec<Vec<u8>> },
+
+    /// Enclave to daemon: the names of the secrets the enclave could unseal.
+    Unsealed { names: Vec<String> },
+
+    /// Any direction: the request could not be handled.
+    Error { message: String },
+}
+
+impl Message {
+    /// The name of the message type, for error reporting.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Message::AttestationRequest { .. } => "AttestationRequest",
+            Message::TargetInfo { .. } => "TargetInfo",
+            Message::Report { .. } => "Report",
+            Message::Quote { .. } => "Quote",
+            Message::KeyExchange { .. } => "KeyExchange",
+            Message::Encrypted { .. } => "Encrypted",
+            Message::QeTargetInfoRequest => "QeTargetInfoRequest",
+            Message::QeTargetInfo { .. } => "QeTargetInfo",
+            Message::QuoteRequest { .. } => "QuoteRequest",
+            Message::Sealed { .. } => "Sealed",
+            Message::Unseal { .. } => "Unseal",
+            Message::Unsealed { .. } => "Unsealed",
+            Message::Error { .. } => "Error",
+        }
+    }
+}
+
+/// This is the error returned when a message cannot be sent or received.
+#[derive(Debug)]
+pub enum Error {
+    /// The underlying stream failed.
+    Io(io::Error),
+
+    /// The payload is not a valid message.
+    Json(serde_json::Error),
+
+    /// The peer speaks a different version of the protocol.
+    Version(u16),
+
+    /// The payload is larger than `MAX_PAYLOAD_LEN`.
+    TooLarge(u32),
+
+    /// The peer sent a message that is not valid at this point of the exchange.
+    Unexpected(&'static str),
+
+    /// The peer sent `Message::Error`.
+    Remote(String),
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Json(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+            Error::Json(e) => write!(f, "invalid message: {}", e),
+            Error::Version(v) => write!(
+                f,
+                "unsupported protocol version {} (expected {})",
+                v, VERSION
+            ),
+            Error::TooLarge(len) => write!(f, "message of {} bytes is too large", len),
+            Error::Unexpected(name) => write!(f, "unexpected {} message", name),
+            Error::Remote(message) => write!(f, "peer reported an error: {}", message),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
+
+/// This writes a single framed message to the stream and flushes it.
+pub fn send<W: Write>(stream: &mut W, message: &Message) -> Result<(), Error> {
+    let payload = serde_json::to_vec(message)?;
+    if payload.len() > MAX_PAYLOAD_LEN as usize {
+        return Err(Error::TooLarge(payload.len() as u32));
+    }
+
+    let mut header = [0u8; HEADER_LEN];
+    header[0..2].copy_from_slice(&VERSION.to_be_bytes());
+    header[2..6].copy_from_slice(&(payload.len() as u32).to_be_bytes());
+
+    stream.write_all(&header)?;
+    stream.write_all(&payload)?;
+    stream.flush()?;
+    Ok(())
+}
+
+/// This reads a single framed message from the stream. It returns `None` if the peer
+/// closed the stream cleanly between messages.
+pub fn recv<R: Read>(stream: &mut R) -> Result<Option<Message>, Error> {
+    let mut header = [0u8; HEADER_LEN];
+    let mut filled = 0;
+    while filled < HEADER_LEN {
+        match stream.read(&mut header[filled..]) {
+            Ok(0) if filled == 0 => return Ok(None),
+            Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+            Ok(n) => filled += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    let version = u16::from_be_bytes([header[0], header[1]]);
+    if version != VERSION {
+        return Err(Error::Version(version));
+    }
+
+    let len = u32::from_be_bytes([header[2], header[3], header[4], header[5]]);
+    if len > MAX_PAYLOAD_LEN {
+        return Err(Error::TooLarge(len));
+    }
+
+    let mut payload = vec![0u8; len as usize];
+    stream.read_exact(&mut payload)?;
+    Ok(Some(serde_json::from_slice(&payload)?))
+}
+
+/// This reads the peer's reply to a request. A closed stream is an error, and so is
+/// `Message::Error`, which is turned into `Error::Remote`.
+pub fn recv_reply<R: Read>(stream: &mut R) -> Result<Message, Error> {
+    match recv(stream)? {
+        Some(Message::Error { message }) => Err(Error::Remote(message)),
+        Some(message) => Ok(message),
+        None => Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+    }
+}
+
+/// The enclave binds the tenant's nonce, together with any data of its own, into its
+/// Report. The first half of the ReportData holds SHA-256(nonce || enclave data); the
+/// second half is left blank.
+pub fn report_data(nonce: &[u8; 32], enclave_data: &[u8]) -> [u8; 64] {
+    let mut hasher = Sha256::new();
+    hasher.input(&nonce[..]);
+    hasher.input(enclave_data);
+
+    let mut report_data = [0u8; 64];
+    report_data[..32].copy_from_slice(&hasher.result());
+    report_data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn frame(version: u16, payload: &[u8]) -> Vec<u8> {
+        let mut frame = version.to_be_bytes().to_vec();
+        frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    fn is_eof(result: Result<Option<Message>, Error>) -> bool {
+        match result {
+            Err(Error::Io(e)) => e.kind() == io::ErrorKind::UnexpectedEof,
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn messages_roundtrip() {
+        let mut stream = Vec::new();
+        send(&mut stream, &Message::AttestationRequest { nonce: [7; 32] }).unwrap();
+        let quote = Message::Quote {
+            quote: vec![1, 2, 3],
+            enclave_data: b"key".to_vec(),
+        };
+        send(&mut stream, &quote).unwrap();
+        assert_eq!(&stream[..2], &VERSION.to_be_bytes());
+
+        let mut stream = Cursor::new(stream);
+        match recv(&mut stream).unwrap() {
+            Some(Message::AttestationRequest { nonce }) => assert_eq!(nonce, [7; 32]),
+            _ => panic!("expected an AttestationRequest"),
+        }
+        match recv(&mut stream).unwrap() {
+            Some(Message::Quote {
+                quote,
+                enclave_data,
+            }) => {
+                assert_eq!(quote, [1, 2, 3]);
+                assert_eq!(enclave_data, b"key");
+            }
+            _ => panic!("expected a Quote"),
+        }
+        assert!(recv(&mut stream).unwrap().is_none());
+    }
+
+    #[test]
+    fn clean_eof_ends_the_stream() {
+        assert!(recv(&mut Cursor::new(Vec::new())).unwrap().is_none());
+        assert!(matches!(
+            recv_reply(&mut Cursor::new(Vec::new())),
+            Err(Error::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof
+        ));
+    }
+
+    #[test]
+    fn truncated_frames_are_errors() {
+        let frame = frame(VERSION, br#""QeTargetInfoRequest""#);
+        assert!(is_eof(recv(&mut Cursor::new(&frame[..3]))));
+        assert!(is_eof(recv(&mut Cursor::new(&frame[..HEADER_LEN]))));
+        assert!(is_eof(recv(&mut Cursor::new(&frame[..frame.len() - 1]))));
+        assert!(recv(&mut Cursor::new(&frame[..])).unwrap().is_some());
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let frame = frame(VERSION + 1, br#""QeTargetInfoRequest""#);
+        assert!(matches!(
+            recv(&mut Cursor::new(frame)),
+            Err(Error::Version(v)) if v == VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn oversized_frames_are_rejected_before_reading_them() {
+        // Only the header is there: reading or buffering the payload would fail with an
+        // I/O error, or try to allocate 4 GiB.
+        let mut header = VERSION.to_be_bytes().to_vec();
+        header.extend_from_slice(&u32::MAX.to_be_bytes());
+        assert!(matches!(
+            recv(&mut Cursor::new(header)),
+            Err(Error::TooLarge(len)) if len == u32::MAX
+        ));
+
+        let mut header = VERSION.to_be_bytes().to_vec();
+        header.extend_from_slice(&(MAX_PAYLOAD_LEN + 1).to_be_bytes());
+        assert!(matches!(
+            recv(&mut Cursor::new(header)),
+            Err(Error::TooLarge(len)) if len == MAX_PAYLOAD_LEN + 1
+        ));
+
+        let message = Message::Encrypted {
+            record: vec![0; MAX_PAYLOAD_LEN as usize],
+        };
+        assert!(matches!(
+            send(&mut Vec::new(), &message),
+            Err(Error::TooLarge(_))
+        ));
+    }
+
+    #[test]
+    fn invalid_json_is_rejected() {
+        for payload in [&b"{not json"[..], br#""NoSuchMessage""#].iter() {
+            assert!(matches!(
+                recv(&mut Cursor::new(frame(VERSION, payload))),
+                Err(Error::Json(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn error_replies_are_remote_errors() {
+        let mut stream = Vec::new();
+        let error = Message::Error {
+            message: "no enclave".to_string(),
+        };
+        send(&mut stream, &error).unwrap();
+        assert!(matches!(
+            recv_reply(&mut Cursor::new(stream)),
+            Err(Error::Remote(message)) if message == "no enclave"
+        ));
+    }
+}
@@ -0,0 +1,39 @@
+//! Object identifiers, as they are written in certificates and their extensions.
+
+/// This encodes an OID, given as its arcs, into the contents of an OBJECT IDENTIFIER: the
+/// first two arcs are combined into one, and every arc is written in base 128, most
+/// significant digit first, with the high bit set on every byte but its last.
+pub fn encode(arcs: &[u64]) -> Vec<u8> {
+    let mut contents = Vec::new();
+    let first = arcs.first().copied().unwrap_or(0) * 40 + arcs.get(1).copied().unwrap_or(0);
+    for &arc in std::iter::once(&first).chain(arcs.iter().skip(2)) {
+        let mut digits = vec![(arc & 0x7f) as u8];
+        let mut rest = arc >> 7;
+        while rest != 0 {
+            digits.push((rest & 0x7f) as u8 | 0x80);
+            rest >>= 7;
+        }
+        contents.extend(digits.iter().rev());
+    }
+    contents
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oids_are_encoded() {
+        // Intel's SGX extension, 1.2.840.113741.1.13.1.
+        assert_eq!(
+            encode(&[1, 2, 840, 113741, 1, 13, 1]),
+            [0x2a, 0x86, 0x48, 0x86, 0xf8, 0x4d, 0x01, 0x0d, 0x01]
+        );
+        // ecdsa-with-SHA256, 1.2.840.10045.4.3.2.
+        assert_eq!(
+            encode(&[1, 2, 840, 10045, 4, 3, 2]),
+            [0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02]
+        );
+        assert_eq!(encode(&[2, 5, 29, 15]), [0x55, 0x1d, 0x0f]);
+    }
+}
@@ -0,0 +1,155 @@
+//! Secret provisioning over the secure channel.
+//!
+//! Once the tenant has verified the Quote and opened the secure channel, it can release
+//! secrets (key material, configuration...) to the enclave. Each secret is sent in a record
+//! of its own as `ChannelMessage::Secret`, and the enclave acknowledges it with
+//! `ChannelMessage::Ack`, which carries the SHA-256 hash of the secret it received, so that
+//! the tenant knows the secret arrived intact. Channel messages are JSON-encoded inside the
+//! records.
+//!
+//! The channel authenticates the enclave, but not the tenant: whoever reaches the enclave
+//! can open a channel and provision secrets. So that a secret cannot be silently replaced,
+//! e.g. by a malicious host, a name can only be provisioned once; the enclave answers any
+//! later secret of the same name with `ChannelMessage::Refused`, and keeps the first one.
+
+use crate::channel::SecureChannel;
+use crate::{recv_reply, send, Error as ProtocolError, Message};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::{Read, Write};
+
+/// A message sent over the secure channel.
+#[derive(Serialize, Deserialize)]
+pub enum ChannelMessage {
+    /// Tenant to enclave: a named secret.
+    Secret { name: String, secret: Vec<u8> },
+
+    /// Enclave to tenant: the secret was received and stored.
+    Ack { name: String, digest: [u8; 32] },
+
+    /// Enclave to tenant: the secret was not stored, as a secret of the same name already
+    /// was.
+    Refused { name: String },
+}
+
+impl ChannelMessage {
+    /// This encrypts the message into the channel's next record.
+    pub fn seal(&self, channel: &mut SecureChannel) -> Result<Vec<u8>, Box<dyn Error>> {
+        Ok(channel.seal(&serde_json::to_vec(self)?)?)
+    }
+
+    /// This decrypts the channel's next record into a message.
+    pub fn open(channel: &mut SecureChannel, record: &[u8]) -> Result<Self, Box<dyn Error>> {
+        Ok(serde_json::from_slice(&channel.open(record)?)?)
+    }
+}
+
+/// This sends a secret to the enclave over the secure channel, relayed by the daemon on the
+/// other end of the stream, and waits for the enclave to acknowledge it.
+pub fn provision<S: Read + Write>(
+    stream: &mut S,
+    channel: &mut SecureChannel,
+    name: &str,
+    secret: &[u8],
+) -> Result<(), Box<dyn Error>> {
+    let record = ChannelMessage::Secret {
+        name: name.to_string(),
+        secret: secret.to_vec(),
+    }
+    .seal(channel)?;
+    send(stream, &Message::Encrypted { record })?;
+
+    let record = match recv_reply(stream)? {
+        Message::Encrypted { record } => record,
+        other => return Err(ProtocolError::Unexpected(other.name()).into()),
+    };
+    match ChannelMessage::open(channel, &record)? {
+        ChannelMessage::Ack {
+            name: acked,
+            digest,
+        } if acked == name && digest[..] == Sha256::digest(secret)[..] => Ok(()),
+        ChannelMessage::Refused { name: refused } if refused == name => Err(format!(
+            "The enclave refused the secret {}: a secret of that name was already provisioned.",
+            name
+        )
+        .into()),
+        _ => Err(format!("The enclave did not acknowledge the secret {}.", name).into()),
+    }
+}
+
+/// What the enclave did with a secret it received from the tenant, along with the record
+/// that tells the tenant.
+pub enum Receipt {
+    /// The secret was stored under its name, and the record acknowledges it.
+    Stored { name: String, record: Vec<u8> },
+
+    /// A secret of the same name was already stored, and was kept; the record refuses the
+    /// new one.
+    Refused { name: String, record: Vec<u8> },
+}
+
+/// The secrets provisioned to the enclave, which it keeps in its memory.
+#[derive(Default)]
+pub struct SecretStore {
+    secrets: BTreeMap<String, Vec<u8>>,
+}
+
+impl SecretStore {
+    pub fn new() -> Self {
+        SecretStore::default()
+    }
+
+    /// This handles a record received from the tenant over the secure channel: the secret
+    /// it carries is stored, unless a secret of the same name already is, and the receipt
+    /// carries the record to send back.
+    pub fn receive(
+        &mut self,
+        channel: &mut SecureChannel,
+        record: &[u8],
+    ) -> Result<Receipt, Box<dyn Error>> {
+        match ChannelMessage::open(channel, record)? {
+            ChannelMessage::Secret { name, .. } if self.secrets.contains_key(&name) => {
+                let record = ChannelMessage::Refused { name: name.clone() }.seal(channel)?;
+                Ok(Receipt::Refused { name, record })
+            }
+            ChannelMessage::Secret { name, secret } => {
+                let mut digest = [0u8; 32];
+                digest.copy_from_slice(&Sha256::digest(&secret));
+                self.secrets.insert(name.clone(), secret);
+                let record = ChannelMessage::Ack {
+                    name: name.clone(),
+                    digest,
+                }
+                .seal(channel)?;
+                Ok(Receipt::Stored { name, record })
+            }
+            ChannelMessage::Ack { .. } => Err(ProtocolError::Unexpected("Ack").into()),
+            ChannelMessage::Refused { .. } => Err(ProtocolError::Unexpected("Refused").into()),
+        }
+    }
+
+    /// This stores a secret that was provisioned earlier, e.g. one the enclave unsealed,
+    /// unless a different secret of the same name is already stored, which is kept. It
+    /// returns whether the store holds this secret under the name.
+    pub fn insert(&mut self, name: String, secret: Vec<u8>) -> bool {
+        match self.secrets.get(&name) {
+            Some(stored) => *stored == secret,
+            None => {
+                self.secrets.insert(name, secret);
+                true
+            }
+        }
+    }
+
+    /// The secret provisioned under the given name, if any.
+    pub fn get(&self, name: &str) -> Option<&[u8]> {
+        self.secrets.get(name).map(|secret| &secret[..])
+    }
+
+    /// The names of the provisioned secrets.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.secrets.keys().map(|name| &name[..])
+    }
+}
@@ -0,0 +1,22 @@
+//! RA-TLS: TLS with a self-signed certificate that carries the enclave's Quote.
+//!
+//! The enclave generates a TLS key, and has its Report bind the hash of the key's
+//! SubjectPublicKeyInfo. The Quote of that Report is embedded in the self-signed certificate
+//! of the key, in a non-critical extension. A tenant that verifies the Quote, and that the
+//! Quote binds the certificate's key, knows that the TLS server it is talking to runs in the
+//! attested enclave: existing TLS clients and servers only need a certificate verifier to
+//! adopt attestation.
+
+use sha2::{Digest, Sha256};
+
+/// The OID of the certificate extension carrying the Quote, 1.2.840.113741.1337.6, as used
+/// by other RA-TLS implementations for DCAP Quotes.
+pub const QUOTE_EXTENSION_OID: &[u64] = &[1, 2, 840, 113741, 1337, 6];
+
+/// The ReportData that binds a TLS key: its first half holds SHA-256 of the DER-encoded
+/// SubjectPublicKeyInfo; the second half is left blank.
+pub fn report_data(public_key_info: &[u8]) -> [u8; 64] {
+    let mut report_data = [0u8; 64];
+    report_data[..32].copy_from_slice(&Sha256::digest(public_key_info));
+    report_data
+}
@@ -0,0 +1,305 @@
+//! The transports the tenant, the daemon and the enclave connect over: TCP, Unix domain
+//! sockets, and vsock, e.g. between a virtual machine hosting the enclave and its host.
+//!
+//! Addresses are written `tcp:HOST:PORT` (or just `HOST:PORT`), `unix:PATH` or
+//! `vsock:CID:PORT`, both on the command line and in configuration files. Inside an SGX
+//! enclave, only TCP is available.
+
+use serde::Deserialize;
+use std::convert::TryFrom;
+use std::error::Error as StdError;
+use std::fmt::{Display, Formatter};
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(target_os = "linux")]
+use vsock::{VsockListener, VsockStream};
+
+/// The address a component listens on, or connects to.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Address {
+    /// A host name or IP address, and a port.
+    Tcp(String),
+
+    /// The path of a Unix domain socket.
+    Unix(PathBuf),
+
+    /// The context ID of a virtual machine (or of the host, 2), and a port.
+    Vsock { cid: u32, port: u32 },
+}
+
+/// This is the error returned when an address cannot be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddressError(String);
+
+impl StdError for AddressError {}
+
+impl Display for AddressError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "invalid address {} (expected tcp:HOST:PORT, unix:PATH or vsock:CID:PORT)",
+            self.0
+        )
+    }
+}
+
+impl FromStr for Address {
+    type Err = AddressError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || AddressError(s.to_string());
+        let address = if let Some(path) = s.strip_prefix("unix:") {
+            Address::Unix(PathBuf::from(path))
+        } else if let Some(vsock) = s.strip_prefix("vsock:") {
+            let mut parts = vsock.splitn(2, ':');
+            let mut next = || parts.next().and_then(|part| part.parse().ok());
+            match (next(), next()) {
+                (Some(cid), Some(port)) => Address::Vsock { cid, port },
+                _ => return Err(error()),
+            }
+        } else {
+            Address::Tcp(s.strip_prefix("tcp:").unwrap_or(s).to_string())
+        };
+
+        match &address {
+            Address::Tcp(host_port) if !host_port.contains(':') => Err(error()),
+            Address::Unix(path) if path.as_os_str().is_empty() => Err(error()),
+            _ => Ok(address),
+        }
+    }
+}
+
+impl TryFrom<String> for Address {
+    type Error = AddressError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl Display for Address {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Address::Tcp(host_port) => write!(f, "{}", host_port),
+            Address::Unix(path) => write!(f, "unix:{}", path.display()),
+            Address::Vsock { cid, port } => write!(f, "vsock:{}:{}", cid, port),
+        }
+    }
+}
+
+/// How long a component waits to connect to a peer, and for the peer to accept or send
+/// data, before giving up on the connection. `Timeouts::default()` sets none; the daemon,
+/// the enclave and the tenant each start from defaults of their own, which their
+/// configuration overrides. In configuration files, timeouts are given in seconds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Timeouts {
+    /// The connect timeout only applies to TCP: Unix domain sockets and vsock connect
+    /// locally.
+    #[serde(deserialize_with = "deserialize_timeout")]
+    pub connect: Option<Duration>,
+
+    #[serde(deserialize_with = "deserialize_timeout")]
+    pub read: Option<Duration>,
+
+    #[serde(deserialize_with = "deserialize_timeout")]
+    pub write: Option<Duration>,
+}
+
+impl Timeouts {
+    /// This returns these timeouts, replaced by those set in overrides, e.g. on the command
+    /// line.
+    pub fn overridden_by(self, overrides: Timeouts) -> Timeouts {
+        Timeouts {
+            connect: overrides.connect.or(self.connect),
+            read: overrides.read.or(self.read),
+            write: overrides.write.or(self.write),
+        }
+    }
+}
+
+/// This parses a timeout in seconds, which must be positive.
+pub fn parse_timeout(seconds: &str) -> Result<Duration, Box<dyn StdError>> {
+    timeout(seconds.parse()?).map_err(Into::into)
+}
+
+fn timeout(seconds: f64) -> Result<Duration, String> {
+    if seconds.is_finite() && seconds > 0.0 {
+        Ok(Duration::from_secs_f64(seconds))
+    } else {
+        Err(format!(
+            "invalid timeout {} (expected positive seconds)",
+            seconds
+        ))
+    }
+}
+
+fn deserialize_timeout<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Duration>, D::Error> {
+    let seconds = f64::deserialize(deserializer)?;
+    timeout(seconds).map(Some).map_err(serde::de::Error::custom)
+}
+
+/// A connection over any of the transports.
+pub enum Stream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+    #[cfg(target_os = "linux")]
+    Vsock(VsockStream),
+}
+
+impl Stream {
+    /// This connects to the address, within the connect timeout, and sets the read and
+    /// write timeouts of the connection.
+    pub fn connect(address: &Address, timeouts: &Timeouts) -> io::Result<Stream> {
+        let stream = match address {
+            Address::Tcp(host_port) => match timeouts.connect {
+                Some(timeout) => Stream::Tcp(connect_tcp(host_port, timeout)?),
+                None => Stream::Tcp(TcpStream::connect(host_port)?),
+            },
+            #[cfg(unix)]
+            Address::Unix(path) => Stream::Unix(UnixStream::connect(path)?),
+            #[cfg(target_os = "linux")]
+            Address::Vsock { cid, port } => {
+                Stream::Vsock(VsockStream::connect_with_cid_port(*cid, *port)?)
+            }
+            #[allow(unreachable_patterns)]
+            _ => return Err(unsupported(address)),
+        };
+        stream.set_timeouts(timeouts)?;
+        Ok(stream)
+    }
+
+    /// This sets the read and write timeouts of the connection.
+    pub fn set_timeouts(&self, timeouts: &Timeouts) -> io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => {
+                stream.set_read_timeout(timeouts.read)?;
+                stream.set_write_timeout(timeouts.write)
+            }
+            #[cfg(unix)]
+            Stream::Unix(stream) => {
+                stream.set_read_timeout(timeouts.read)?;
+                stream.set_write_timeout(timeouts.write)
+            }
+            #[cfg(target_os = "linux")]
+            Stream::Vsock(stream) => {
+                stream.set_read_timeout(timeouts.read)?;
+                stream.set_write_timeout(timeouts.write)
+            }
+        }
+    }
+}
+
+/// This tries every address the host name resolves to, within the timeout each.
+fn connect_tcp(host_port: &str, timeout: Duration) -> io::Result<TcpStream> {
+    let mut error = io::Error::new(io::ErrorKind::NotFound, "host name did not resolve");
+    for socket_address in host_port.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&socket_address, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => error = e,
+        }
+    }
+    Err(error)
+}
+
+fn unsupported(address: &Address) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("{} is not supported on this platform", address),
+    )
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.read(buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.read(buf),
+            #[cfg(target_os = "linux")]
+            Stream::Vsock(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.write(buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.write(buf),
+            #[cfg(target_os = "linux")]
+            Stream::Vsock(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.flush(),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.flush(),
+            #[cfg(target_os = "linux")]
+            Stream::Vsock(stream) => stream.flush(),
+        }
+    }
+}
+
+/// A listener on any of the transports.
+pub enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+    #[cfg(target_os = "linux")]
+    Vsock(VsockListener),
+}
+
+impl Listener {
+    /// This listens on the address. A Unix domain socket left over by an earlier run is
+    /// replaced.
+    pub fn bind(address: &Address) -> io::Result<Listener> {
+        match address {
+            Address::Tcp(host_port) => Ok(Listener::Tcp(TcpListener::bind(host_port)?)),
+            #[cfg(unix)]
+            Address::Unix(path) => {
+                use std::os::unix::fs::FileTypeExt;
+                if let Ok(metadata) = std::fs::symlink_metadata(path) {
+                    if metadata.file_type().is_socket() {
+                        std::fs::remove_file(path)?;
+                    }
+                }
+                Ok(Listener::Unix(UnixListener::bind(path)?))
+            }
+            #[cfg(target_os = "linux")]
+            Address::Vsock { cid, port } => Ok(Listener::Vsock(VsockListener::bind_with_cid_port(
+                *cid, *port,
+            )?)),
+            #[allow(unreachable_patterns)]
+            _ => Err(unsupported(address)),
+        }
+    }
+
+    /// This waits for the next connection.
+    pub fn accept(&self) -> io::Result<Stream> {
+        match self {
+            Listener::Tcp(listener) => Ok(Stream::Tcp(listener.accept()?.0)),
+            #[cfg(unix)]
+            Listener::Unix(listener) => Ok(Stream::Unix(listener.accept()?.0)),
+            #[cfg(target_os = "linux")]
+            Listener::Vsock(listener) => Ok(Stream::Vsock(listener.accept()?.0)),
+        }
+    }
+
+    /// The connections, as they come.
+    pub fn incoming(&self) -> impl Iterator<Item = io::Result<Stream>> + '_ {
+        std::iter::repeat_with(move || self.accept())
+    }
+}
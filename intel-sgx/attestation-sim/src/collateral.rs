@@ -0,0 +1,83 @@
+use crate::{
+    pki::{sign_raw, Platform, TestPki},
+    time,
+};
+use serde_json::{json, Map, Value};
+use sgx_isa::Report;
+use std::error::Error;
+
+/// The collateral is valid for a month from its issue.
+const DAYS_VALID: i64 = 30;
+
+/// This issues TCB Info (version 2) for the platform's FMSPC, with a single TCB level: the
+/// platform's, which is UpToDate.
+pub fn tcb_info(pki: &TestPki, platform: &Platform) -> Result<String, Box<dyn Error>> {
+    let mut tcb = Map::new();
+    for (i, svn) in platform.comp_svn.iter().enumerate() {
+        tcb.insert(format!("sgxtcbcomp{:02}svn", i + 1), json!(svn));
+    }
+    tcb.insert("pcesvn".to_string(), json!(platform.pce_svn));
+
+    let now = time::now();
+    let body = json!({
+        "version": 2,
+        "issueDate": time::iso8601(now),
+        "nextUpdate": time::iso8601(now + DAYS_VALID * time::DAY),
+        "fmspc": hex::encode(platform.fmspc),
+        "pceId": hex::encode(platform.pce_id),
+        "tcbType": 0,
+        "tcbEvaluationDataNumber": 1,
+        "tcbLevels": [{
+            "tcb": Value::Object(tcb),
+            "tcbDate": time::iso8601(now),
+            "tcbStatus": "UpToDate",
+        }],
+    });
+    signed_json(pki, "tcbInfo", &body)
+}
+
+/// This issues an Enclave Identity (version 2) for the enclave with the given id, which is QE
+/// for the QE Identity, describing the Quoting Enclave that produces qe_report, whose ISVSVN
+/// is UpToDate.
+pub fn enclave_identity(
+    pki: &TestPki,
+    id: &str,
+    qe_report: &Report,
+) -> Result<String, Box<dyn Error>> {
+    let mut attributes = qe_report.attributes.flags.bits().to_le_bytes().to_vec();
+    attributes.extend(&qe_report.attributes.xfrm.to_le_bytes());
+
+    let now = time::now();
+    let body = json!({
+        "id": id,
+        "version": 2,
+        "issueDate": time::iso8601(now),
+        "nextUpdate": time::iso8601(now + DAYS_VALID * time::DAY),
+        "tcbEvaluationDataNumber": 1,
+        "miscselect": format!("{:08x}", qe_report.miscselect.bits()),
+        "miscselectMask": "ffffffff",
+        "attributes": hex::encode(attributes),
+        "attributesMask": "fbffffffffffffff0000000000000000",
+        "mrsigner": hex::encode(qe_report.mrsigner),
+        "isvprodid": qe_report.isvprodid,
+        "tcbLevels": [{
+            "tcb": { "isvsvn": qe_report.isvsvn },
+            "tcbDate": time::iso8601(now),
+            "tcbStatus": "UpToDate",
+        }],
+    });
+    signed_json(pki, "enclaveIdentity", &body)
+}
+
+/// Intel's collateral is a body, named name, and the TCB Signing Key's signature over the
+/// exact bytes of the body.
+fn signed_json(pki: &TestPki, name: &str, body: &Value) -> Result<String, Box<dyn Error>> {
+    let body = serde_json::to_string(body)?;
+    let signature = sign_raw(&pki.tcb_signing.key, body.as_bytes())?;
+    Ok(format!(
+        "{{\"{}\":{},\"signature\":\"{}\"}}",
+        name,
+        body,
+        hex::encode(signature)
+    ))
+}
@@ -0,0 +1,73 @@
+//! A minimal DER encoder, for the structures that openssl cannot build: the SGX extension
+//! of the PCK Certificate and the CRLs.
+
+pub const INTEGER: u8 = 0x02;
+pub const BIT_STRING: u8 = 0x03;
+pub const OCTET_STRING: u8 = 0x04;
+pub const OBJECT_IDENTIFIER: u8 = 0x06;
+pub const ENUMERATED: u8 = 0x0a;
+pub const UTC_TIME: u8 = 0x17;
+pub const SEQUENCE: u8 = 0x30;
+
+/// This encodes an element from its tag and contents.
+pub fn tlv(tag: u8, contents: &[u8]) -> Vec<u8> {
+    let mut element = vec![tag];
+    if contents.len() < 0x80 {
+        element.push(contents.len() as u8);
+    } else {
+        let len = (contents.len() as u32).to_be_bytes();
+        let len = &len[len.iter().take_while(|&&b| b == 0).count()..];
+        element.push(0x80 | len.len() as u8);
+        element.extend(len);
+    }
+    element.extend(contents);
+    element
+}
+
+pub fn sequence(elements: &[Vec<u8>]) -> Vec<u8> {
+    tlv(SEQUENCE, &elements.concat())
+}
+
+/// This encodes an OBJECT IDENTIFIER from its encoded arcs.
+pub fn oid(encoded: &[u8]) -> Vec<u8> {
+    tlv(OBJECT_IDENTIFIER, encoded)
+}
+
+pub fn octet_string(bytes: &[u8]) -> Vec<u8> {
+    tlv(OCTET_STRING, bytes)
+}
+
+/// This encodes a BIT STRING with no unused bits.
+pub fn bit_string(bytes: &[u8]) -> Vec<u8> {
+    let mut contents = vec![0];
+    contents.extend(bytes);
+    tlv(BIT_STRING, &contents)
+}
+
+pub fn integer(value: u64) -> Vec<u8> {
+    tlv(INTEGER, &unsigned(&value.to_be_bytes()))
+}
+
+/// This encodes a non-negative INTEGER from its big-endian bytes.
+pub fn integer_bytes(value: &[u8]) -> Vec<u8> {
+    tlv(INTEGER, &unsigned(value))
+}
+
+pub fn enumerated(value: u64) -> Vec<u8> {
+    tlv(ENUMERATED, &unsigned(&value.to_be_bytes()))
+}
+
+pub fn utc_time(time: &str) -> Vec<u8> {
+    tlv(UTC_TIME, time.as_bytes())
+}
+
+/// The contents of a non-negative INTEGER are minimal, and start with a zero byte if the
+/// high bit of the value is set.
+fn unsigned(value: &[u8]) -> Vec<u8> {
+    let value = &value[value.iter().take_while(|&&b| b == 0).count()..];
+    match value.first() {
+        None => vec![0],
+        Some(b) if b & 0x80 != 0 => [&[0], value].concat(),
+        Some(_) => value.to_vec(),
+    }
+}
@@ -0,0 +1,22 @@
+//! A simulation of the SGX attestation infrastructure, for running and testing attestation
+//! without SGX hardware: a PKI shaped like Intel's, with a PCK Certificate for a simulated
+//! platform, the collateral (CRLs, TCB Info and QE Identity) issued by it, simulated
+//! enclaves producing MACed Reports, and a simulated Quoting Enclave turning them into
+//! version 3 ECDSA Quotes.
+//!
+//! Quotes from the simulated Quoting Enclave are only trusted by a tenant configured with
+//! the simulated PKI's root and collateral, e.g. as written by
+//! `QuotingEnclave::write_collateral`. They carry no guarantee whatsoever.
+
+mod collateral;
+mod der;
+mod pki;
+mod qe;
+mod quote;
+mod report;
+mod time;
+
+pub use pki::{Credential, Platform, TestPki};
+pub use qe::{QuotingEnclave, QE_ISVPRODID, QE_ISVSVN, QE_MRENCLAVE, QE_MRSIGNER};
+pub use quote::{Defects, QuoteBuilder};
+pub use report::{verify_report, EnclaveIdentity};
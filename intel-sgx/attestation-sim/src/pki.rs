@@ -0,0 +1,283 @@
+use crate::{der, time};
+use attestation_protocol::oid;
+use openssl::{
+    asn1::{Asn1Integer, Asn1Object, Asn1OctetString, Asn1Time},
+    bn::{BigNum, MsbOption},
+    ec::{EcGroup, EcKey},
+    hash::MessageDigest,
+    nid::Nid,
+    pkey::{PKey, PKeyRef, Private},
+    sign::Signer,
+    x509::{
+        extension::{AuthorityKeyIdentifier, BasicConstraints, KeyUsage, SubjectKeyIdentifier},
+        X509Builder, X509Extension, X509Name, X509,
+    },
+};
+use std::error::Error;
+
+/// The OID of Intel's SGX extension, 1.2.840.113741.1.13.1.
+const SGX_EXTENSION_OID: &[u64] = &[1, 2, 840, 113741, 1, 13, 1];
+
+/// The OID of ecdsa-with-SHA256, 1.2.840.10045.4.3.2.
+const ECDSA_WITH_SHA256_OID: &[u64] = &[1, 2, 840, 10045, 4, 3, 2];
+
+/// The simulated platform, as certified by its PCK Certificate.
+#[derive(Debug, Clone)]
+pub struct Platform {
+    pub ppid: [u8; 16],
+    pub fmspc: [u8; 6],
+    pub pce_id: [u8; 2],
+
+    /// The SVNs of the 16 CPU TCB components.
+    pub comp_svn: [u8; 16],
+    pub pce_svn: u16,
+    pub cpu_svn: [u8; 16],
+}
+
+impl Default for Platform {
+    fn default() -> Self {
+        let comp_svn = [5, 5, 2, 2, 4, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        Platform {
+            ppid: [0x5a; 16],
+            fmspc: [0x00, 0x90, 0x6e, 0xa1, 0x00, 0x00],
+            pce_id: [0, 0],
+            comp_svn,
+            pce_svn: 9,
+            cpu_svn: comp_svn,
+        }
+    }
+}
+
+impl Platform {
+    /// This encodes the platform as the SGX extension of a PCK Certificate. For its
+    /// definition, you may refer to:
+    /// https://download.01.org/intel-sgx/dcap-1.0/docs/SGX_PCK_Certificate_CRL_Spec-1.0.pdf
+    pub fn sgx_extension(&self) -> Vec<u8> {
+        let field = |arcs: &[u64], value: Vec<u8>| {
+            let field_oid = oid::encode(&[SGX_EXTENSION_OID, arcs].concat());
+            der::sequence(&[der::oid(&field_oid), value])
+        };
+
+        let mut tcb: Vec<Vec<u8>> = self
+            .comp_svn
+            .iter()
+            .enumerate()
+            .map(|(i, &svn)| field(&[2, i as u64 + 1], der::integer(u64::from(svn))))
+            .collect();
+        tcb.push(field(&[2, 17], der::integer(u64::from(self.pce_svn))));
+        tcb.push(field(&[2, 18], der::octet_string(&self.cpu_svn)));
+
+        der::sequence(&[
+            field(&[1], der::octet_string(&self.ppid)),
+            field(&[2], der::sequence(&tcb)),
+            field(&[3], der::octet_string(&self.pce_id)),
+            field(&[4], der::octet_string(&self.fmspc)),
+            field(&[5], der::enumerated(0)),
+        ])
+    }
+}
+
+/// A certificate and its private key.
+pub struct Credential {
+    pub cert: X509,
+    pub key: PKey<Private>,
+}
+
+impl Credential {
+    /// This issues a certificate for a new P-256 key. Without an issuer, the certificate is
+    /// self-signed. The certificate is valid from a month ago to days_valid days from now, so
+    /// a negative days_valid issues an expired certificate.
+    pub fn issue(
+        common_name: &str,
+        issuer: Option<&Credential>,
+        is_ca: bool,
+        days_valid: i64,
+        sgx_extension: Option<&[u8]>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let key = new_key()?;
+
+        let mut name = X509Name::builder()?;
+        name.append_entry_by_nid(Nid::ORGANIZATIONNAME, "attestation-sim")?;
+        name.append_entry_by_nid(Nid::COMMONNAME, common_name)?;
+        let name = name.build();
+
+        let mut serial = BigNum::new()?;
+        serial.rand(127, MsbOption::MAYBE_ZERO, false)?;
+
+        let serial = Asn1Integer::from_bn(&serial)?;
+        let not_before = Asn1Time::from_unix(time::now() - 30 * time::DAY)?;
+        let not_after = Asn1Time::from_unix(time::now() + days_valid * time::DAY)?;
+
+        let mut builder = X509Builder::new()?;
+        builder.set_version(2)?;
+        builder.set_serial_number(&serial)?;
+        builder.set_subject_name(&name)?;
+        builder.set_issuer_name(match issuer {
+            Some(issuer) => issuer.cert.subject_name(),
+            None => &name,
+        })?;
+        builder.set_pubkey(&key)?;
+        builder.set_not_before(&not_before)?;
+        builder.set_not_after(&not_after)?;
+
+        if is_ca {
+            builder.append_extension(BasicConstraints::new().critical().ca().build()?)?;
+            builder.append_extension(
+                KeyUsage::new()
+                    .critical()
+                    .key_cert_sign()
+                    .crl_sign()
+                    .build()?,
+            )?;
+        } else {
+            builder.append_extension(
+                KeyUsage::new()
+                    .critical()
+                    .digital_signature()
+                    .non_repudiation()
+                    .build()?,
+            )?;
+        }
+        let ski = SubjectKeyIdentifier::new().build(&builder.x509v3_context(None, None))?;
+        builder.append_extension(ski)?;
+        if let Some(issuer) = issuer {
+            let aki = AuthorityKeyIdentifier::new()
+                .keyid(true)
+                .build(&builder.x509v3_context(Some(&issuer.cert), None))?;
+            builder.append_extension(aki)?;
+        }
+        if let Some(extension) = sgx_extension {
+            let oid = Asn1Object::from_str("1.2.840.113741.1.13.1")?;
+            let value = Asn1OctetString::new_from_bytes(extension)?;
+            builder.append_extension(X509Extension::new_from_der(&oid, false, &value)?)?;
+        }
+
+        let signing_key = issuer.map(|issuer| &issuer.key).unwrap_or(&key);
+        builder.sign(signing_key, MessageDigest::sha256())?;
+
+        Ok(Credential {
+            cert: builder.build(),
+            key,
+        })
+    }
+
+    /// This issues a DER-encoded CRL listing the revoked certificates, which is current for
+    /// days_valid days.
+    pub fn issue_crl(&self, revoked: &[&X509], days_valid: i64) -> Result<Vec<u8>, Box<dyn Error>> {
+        let this_update = time::utc_time(time::now() - time::DAY);
+        let next_update = time::utc_time(time::now() + days_valid * time::DAY);
+        let algorithm = der::sequence(&[der::oid(&oid::encode(ECDSA_WITH_SHA256_OID))]);
+
+        let mut tbs = vec![
+            der::integer(1),
+            algorithm.clone(),
+            self.cert.subject_name().to_der()?,
+            der::utc_time(&this_update),
+            der::utc_time(&next_update),
+        ];
+        if !revoked.is_empty() {
+            let mut entries = Vec::new();
+            for cert in revoked {
+                let serial = cert.serial_number().to_bn()?.to_vec();
+                entries.push(der::sequence(&[
+                    der::integer_bytes(&serial),
+                    der::utc_time(&this_update),
+                ]));
+            }
+            tbs.push(der::sequence(&entries));
+        }
+        let tbs = der::sequence(&tbs);
+
+        let mut signer = Signer::new(MessageDigest::sha256(), &self.key)?;
+        signer.update(&tbs)?;
+        let signature = signer.sign_to_vec()?;
+
+        Ok(der::sequence(&[
+            tbs,
+            algorithm,
+            der::bit_string(&signature),
+        ]))
+    }
+}
+
+/// A PKI shaped like Intel's: a root CA, a PCK Platform CA issuing the platform's PCK
+/// Certificate, and the TCB Signing Certificate that signs TCB Info and QE Identity. None of
+/// its keys are Intel's, so it is only trusted by tenants configured with its root.
+pub struct TestPki {
+    pub root: Credential,
+    pub pck_ca: Credential,
+    pub pck: Credential,
+    pub tcb_signing: Credential,
+}
+
+impl TestPki {
+    /// This generates the PKI, with a PCK Certificate for the given platform.
+    pub fn generate(platform: &Platform) -> Result<Self, Box<dyn Error>> {
+        let root = Credential::issue("Simulated SGX Root CA", None, true, 3650, None)?;
+        let pck_ca = Credential::issue(
+            "Simulated SGX PCK Platform CA",
+            Some(&root),
+            true,
+            3650,
+            None,
+        )?;
+        let pck = Credential::issue(
+            "Simulated SGX PCK Certificate",
+            Some(&pck_ca),
+            false,
+            3650,
+            Some(&platform.sgx_extension()),
+        )?;
+        let tcb_signing =
+            Credential::issue("Simulated SGX TCB Signing", Some(&root), false, 3650, None)?;
+        Ok(TestPki {
+            root,
+            pck_ca,
+            pck,
+            tcb_signing,
+        })
+    }
+
+    /// The tenant's PCK cert chain: the PCK Platform CA followed by the root.
+    pub fn pck_chain_pem(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        pem(&[&self.pck_ca.cert, &self.root.cert])
+    }
+
+    /// The TCB signing chain: the TCB Signing Certificate followed by the root.
+    pub fn tcb_chain_pem(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        pem(&[&self.tcb_signing.cert, &self.root.cert])
+    }
+
+    /// The Quote's Certification Data: the whole PCK cert chain, from the PCK Certificate
+    /// to the root.
+    pub fn cert_data(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        pem(&[&self.pck.cert, &self.pck_ca.cert, &self.root.cert])
+    }
+}
+
+/// This generates a new P-256 private key.
+pub fn new_key() -> Result<PKey<Private>, Box<dyn Error>> {
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?;
+    Ok(PKey::from_ec_key(EcKey::generate(&group)?)?)
+}
+
+/// This signs the SHA-256 hash of data, and returns the raw r || s signature found in
+/// Quotes and Intel's collateral.
+pub fn sign_raw(key: &PKeyRef<Private>, data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let digest = openssl::sha::sha256(data);
+    let signature = openssl::ecdsa::EcdsaSig::sign(&digest, &*key.ec_key()?)?;
+    Ok([
+        signature.r().to_vec_padded(32)?,
+        signature.s().to_vec_padded(32)?,
+    ]
+    .concat())
+}
+
+/// This concatenates the PEM encodings of the certificates.
+pub fn pem(certs: &[&X509]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut pem = Vec::new();
+    for cert in certs {
+        pem.extend(cert.to_pem()?);
+    }
+    Ok(pem)
+}
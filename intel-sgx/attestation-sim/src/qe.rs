@@ -0,0 +1,128 @@
+use crate::{
+    collateral,
+    pki::{new_key, Platform, TestPki},
+    quote::QuoteBuilder,
+    report::verify_report,
+};
+use openssl::pkey::{PKey, Private};
+use sgx_isa::{Attributes, AttributesFlags, Report, Targetinfo};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// The measurements of the simulated Quoting Enclave. They are not those of Intel's.
+pub const QE_MRENCLAVE: [u8; 32] = [0x51; 32];
+pub const QE_MRSIGNER: [u8; 32] = [0x5e; 32];
+pub const QE_ISVPRODID: u16 = 1;
+pub const QE_ISVSVN: u16 = 2;
+
+/// The QE Authentication Data, as the DCAP Quoting Enclave sets it by default.
+const AUTH_DATA: [u8; 32] = [
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25,
+    26, 27, 28, 29, 30, 31,
+];
+
+/// A simulated Quoting Enclave, for running attestation without SGX hardware. Its Attestation
+/// Key is certified by the PCK of a simulated platform, whose PCK Certificate is issued by a
+/// generated TestPki, and the collateral the tenant needs to verify its Quotes is issued by
+/// the same TestPki.
+pub struct QuotingEnclave {
+    platform: Platform,
+    pki: TestPki,
+    attestation_key: PKey<Private>,
+    report: Report,
+}
+
+impl QuotingEnclave {
+    /// This generates a Quoting Enclave, its Attestation Key and a TestPki for the platform.
+    pub fn new(platform: Platform) -> Result<Self, Box<dyn Error>> {
+        let pki = TestPki::generate(&platform)?;
+
+        let report = Report {
+            cpusvn: platform.cpu_svn,
+            attributes: Attributes {
+                flags: AttributesFlags::INIT | AttributesFlags::MODE64BIT,
+                xfrm: 0x3,
+            },
+            mrenclave: QE_MRENCLAVE,
+            mrsigner: QE_MRSIGNER,
+            isvprodid: QE_ISVPRODID,
+            isvsvn: QE_ISVSVN,
+            ..Default::default()
+        };
+
+        Ok(QuotingEnclave {
+            platform,
+            pki,
+            attestation_key: new_key()?,
+            report,
+        })
+    }
+
+    pub fn platform(&self) -> &Platform {
+        &self.platform
+    }
+
+    pub fn pki(&self) -> &TestPki {
+        &self.pki
+    }
+
+    /// The Quoting Enclave's Target Info, which enclaves target their Reports at.
+    pub fn target_info(&self) -> Targetinfo {
+        Targetinfo::from(self.report.clone())
+    }
+
+    /// This returns a QuoteBuilder for a Quote of the given Report, which can be altered
+    /// before the Quote is built.
+    pub fn quote_builder(&self, report: &Report) -> Result<QuoteBuilder, Box<dyn Error>> {
+        Ok(QuoteBuilder {
+            report: report.clone(),
+            qe_report: self.report.clone(),
+            qe_svn: self.report.isvsvn,
+            pce_svn: self.platform.pce_svn,
+            attestation_key: self.attestation_key.clone(),
+            pck_key: self.pki.pck.key.clone(),
+            cert_data: self.pki.cert_data()?,
+            auth_data: AUTH_DATA.to_vec(),
+            defects: Default::default(),
+        })
+    }
+
+    /// This produces a Quote of the Report. Like Intel's, the Quoting Enclave only quotes
+    /// Reports targeted at it.
+    pub fn quote(&self, report: &Report) -> Result<Vec<u8>, Box<dyn Error>> {
+        verify_report(report, &self.report.mrenclave)?;
+        self.quote_builder(report)?.build()
+    }
+
+    /// TCB Info for the platform, signed by the TestPki's TCB Signing Key.
+    pub fn tcb_info(&self) -> Result<String, Box<dyn Error>> {
+        collateral::tcb_info(&self.pki, &self.platform)
+    }
+
+    /// QE Identity for this Quoting Enclave, signed by the TestPki's TCB Signing Key.
+    pub fn qe_identity(&self) -> Result<String, Box<dyn Error>> {
+        self.enclave_identity("QE")
+    }
+
+    /// An Enclave Identity describing this Quoting Enclave, signed by the TestPki's TCB
+    /// Signing Key, but issued for the enclave with the given id (e.g. QVE or TD_QE): only
+    /// the one for QE is a QE Identity.
+    pub fn enclave_identity(&self, id: &str) -> Result<String, Box<dyn Error>> {
+        collateral::enclave_identity(&self.pki, id, &self.report)
+    }
+
+    /// This writes everything a tenant needs to verify this Quoting Enclave's Quotes to dir,
+    /// under the file names used in the README: pck_chain.pem, root_ca.crl, pck_ca.crl,
+    /// tcb_info.json, tcb_chain.pem and qe_identity.json.
+    pub fn write_collateral(&self, dir: &Path) -> Result<(), Box<dyn Error>> {
+        fs::create_dir_all(dir)?;
+        fs::write(dir.join("pck_chain.pem"), self.pki.pck_chain_pem()?)?;
+        fs::write(dir.join("root_ca.crl"), self.pki.root.issue_crl(&[], 30)?)?;
+        fs::write(dir.join("pck_ca.crl"), self.pki.pck_ca.issue_crl(&[], 30)?)?;
+        fs::write(dir.join("tcb_info.json"), self.tcb_info()?)?;
+        fs::write(dir.join("tcb_chain.pem"), self.pki.tcb_chain_pem()?)?;
+        fs::write(dir.join("qe_identity.json"), self.qe_identity()?)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,130 @@
+use crate::pki::{pem, sign_raw, Credential};
+use openssl::{
+    bn::{BigNum, BigNumContext},
+    pkey::{PKey, PKeyRef, Private},
+    sha::sha256,
+    x509::X509,
+};
+use sgx_isa::Report;
+use std::error::Error;
+
+const QUOTE_VERSION: u16 = 3;
+const ECDSA_P256: u16 = 2;
+const CERT_DATA_PCK_CERT_CHAIN: u16 = 5;
+
+/// The QE Vendor ID of Intel's Quoting Enclave.
+const QE_VENDOR_ID: [u8; 16] = [
+    0x93, 0x9a, 0x72, 0x33, 0xf7, 0x9c, 0x4c, 0xa9, 0x94, 0x0a, 0x0d, 0xb3, 0x95, 0x7f, 0x06, 0x07,
+];
+
+/// The Report body is the Report without its KEYID and MAC.
+const REPORT_BODY_SIZE: usize = 384;
+
+/// This builds a version 3 ECDSA Quote, laid out as described in:
+/// https://download.01.org/intel-sgx/dcap-1.0/docs/SGX_ECDSA_QuoteGenReference_DCAP_API_Linux_1.0.pdf
+///
+/// The Quote is signed with the Attestation Key, and the Quoting Enclave's Report, which
+/// binds the Attestation Key, is signed with the PCK.
+pub struct QuoteBuilder {
+    pub report: Report,
+    pub qe_report: Report,
+    pub qe_svn: u16,
+    pub pce_svn: u16,
+    pub attestation_key: PKey<Private>,
+    pub pck_key: PKey<Private>,
+
+    /// The Certification Data: the PEM-encoded PCK cert chain, from the PCK Certificate to
+    /// the root.
+    pub cert_data: Vec<u8>,
+    pub auth_data: Vec<u8>,
+
+    /// Deliberate defects, for testing verifiers.
+    pub defects: Defects,
+}
+
+/// Defects that a QuoteBuilder can introduce in the Quote. Each one should cause exactly one
+/// check of a Quote verifier to fail.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Defects {
+    /// The Attestation Key's signature on the Quote is corrupted.
+    pub bad_isv_signature: bool,
+
+    /// The PCK's signature on the QE Report is corrupted.
+    pub bad_qe_report_signature: bool,
+
+    /// The QE Report's ReportData does not hold the hash of the Attestation Key and the QE
+    /// Authentication Data.
+    pub wrong_qe_report_data: bool,
+}
+
+impl QuoteBuilder {
+    /// This has the Quote certified by another PCK Certificate, whose chain (from its issuer
+    /// to the root) is given.
+    pub fn set_pck(&mut self, pck: &Credential, chain: &[&X509]) -> Result<(), Box<dyn Error>> {
+        let mut certs = vec![&pck.cert];
+        certs.extend(chain);
+        self.cert_data = pem(&certs)?;
+        self.pck_key = pck.key.clone();
+        Ok(())
+    }
+
+    pub fn build(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut quote = Vec::new();
+        quote.extend(&QUOTE_VERSION.to_le_bytes());
+        quote.extend(&ECDSA_P256.to_le_bytes());
+        quote.extend(&[0u8; 4]);
+        quote.extend(&self.qe_svn.to_le_bytes());
+        quote.extend(&self.pce_svn.to_le_bytes());
+        quote.extend(&QE_VENDOR_ID);
+        quote.extend(&[0u8; 20]);
+        quote.extend(&self.report.as_ref()[..REPORT_BODY_SIZE]);
+
+        // The first half of the QE Report's ReportData is the hash of the Attestation Key
+        // and the QE Authentication Data.
+        let attestation_key = public_key_xy(&self.attestation_key)?;
+        let mut qe_report = self.qe_report.clone();
+        qe_report.reportdata = [0u8; 64];
+        qe_report.reportdata[..32].copy_from_slice(&sha256(
+            &[&attestation_key[..], &self.auth_data[..]].concat(),
+        ));
+        if self.defects.wrong_qe_report_data {
+            qe_report.reportdata[0] ^= 0xff;
+        }
+        let qe_report = &qe_report.as_ref()[..REPORT_BODY_SIZE];
+
+        let mut signature_data = Vec::new();
+        let mut isv_signature = sign_raw(&self.attestation_key, &quote)?;
+        if self.defects.bad_isv_signature {
+            isv_signature[31] ^= 0x01;
+        }
+        let mut qe_report_signature = sign_raw(&self.pck_key, qe_report)?;
+        if self.defects.bad_qe_report_signature {
+            qe_report_signature[31] ^= 0x01;
+        }
+
+        signature_data.extend(isv_signature);
+        signature_data.extend(&attestation_key);
+        signature_data.extend(qe_report);
+        signature_data.extend(qe_report_signature);
+        signature_data.extend(&(self.auth_data.len() as u16).to_le_bytes());
+        signature_data.extend(&self.auth_data);
+        signature_data.extend(&CERT_DATA_PCK_CERT_CHAIN.to_le_bytes());
+        signature_data.extend(&(self.cert_data.len() as u32).to_le_bytes());
+        signature_data.extend(&self.cert_data);
+
+        quote.extend(&(signature_data.len() as u32).to_le_bytes());
+        quote.extend(signature_data);
+        Ok(quote)
+    }
+}
+
+/// This returns a P-256 public key as raw x || y coordinates.
+fn public_key_xy(key: &PKeyRef<Private>) -> Result<Vec<u8>, Box<dyn Error>> {
+    let key = key.ec_key()?;
+    let mut x = BigNum::new()?;
+    let mut y = BigNum::new()?;
+    let mut ctx = BigNumContext::new()?;
+    key.public_key()
+        .affine_coordinates_gfp(key.group(), &mut x, &mut y, &mut ctx)?;
+    Ok([x.to_vec_padded(32)?, y.to_vec_padded(32)?].concat())
+}
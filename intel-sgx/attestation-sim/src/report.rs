@@ -0,0 +1,157 @@
+use openssl::{pkey::PKey, sign::Signer, symm::Cipher};
+use sgx_isa::{
+    Attributes, AttributesFlags, Keyname, Keypolicy, Keyrequest, Miscselect, Report, Targetinfo,
+};
+use std::error::Error;
+
+/// The simulated platform's secret, from which the keys of its enclaves are derived.
+/// Unlike the CPU's, it is public: a simulated Report proves nothing.
+const PLATFORM_SECRET: [u8; 16] = *b"attestation-sim!";
+
+/// The label of the simulated SEAL keys' derivation data.
+const SEAL_KEY_LABEL: &[u8] = b"attestation-sim seal key";
+
+/// The Report body, which the MAC covers, is the Report without its KEYID and MAC.
+const REPORT_BODY_SIZE: usize = 384;
+
+/// The identity of a simulated enclave, which it reports in its Reports.
+#[derive(Debug, Clone)]
+pub struct EnclaveIdentity {
+    pub mrenclave: [u8; 32],
+    pub mrsigner: [u8; 32],
+    pub attributes: Attributes,
+    pub miscselect: Miscselect,
+    pub isvprodid: u16,
+    pub isvsvn: u16,
+    pub cpusvn: [u8; 16],
+}
+
+impl Default for EnclaveIdentity {
+    fn default() -> Self {
+        EnclaveIdentity {
+            mrenclave: [0xe0; 32],
+            mrsigner: [0xe5; 32],
+            attributes: Attributes {
+                flags: AttributesFlags::INIT | AttributesFlags::MODE64BIT,
+                xfrm: 0x3,
+            },
+            miscselect: Miscselect::default(),
+            isvprodid: 0,
+            isvsvn: 0,
+            cpusvn: [0; 16],
+        }
+    }
+}
+
+impl EnclaveIdentity {
+    /// This simulates EREPORT: the Report describes the enclave, carries the report_data and
+    /// is MACed with the REPORT key of the target enclave, which only the target can derive.
+    pub fn report(
+        &self,
+        target_info: &Targetinfo,
+        report_data: &[u8; 64],
+    ) -> Result<Report, Box<dyn Error>> {
+        let mut report = Report {
+            cpusvn: self.cpusvn,
+            miscselect: self.miscselect,
+            attributes: self.attributes,
+            mrenclave: self.mrenclave,
+            mrsigner: self.mrsigner,
+            isvprodid: self.isvprodid,
+            isvsvn: self.isvsvn,
+            reportdata: *report_data,
+            ..Default::default()
+        };
+        report.mac = mac(&report, &target_info.measurement)?;
+        Ok(report)
+    }
+
+    /// The enclave's Target Info, at which other enclaves target the Reports they send it.
+    pub fn target_info(&self) -> Targetinfo {
+        Targetinfo {
+            measurement: self.mrenclave,
+            attributes: self.attributes,
+            miscselect: self.miscselect,
+            ..Default::default()
+        }
+    }
+
+    /// This simulates EGETKEY for REPORT and SEAL keys.
+    ///
+    /// The REPORT key is the one the enclave's Reports are MACed with, by whichever enclave
+    /// targets them at it. It only depends on the enclave's MRENCLAVE.
+    ///
+    /// As on the CPU, the SEAL key is bound to the enclave's MRENCLAVE and/or MRSIGNER as
+    /// the key policy selects, to its ISVPRODID, and to the requested ISVSVN and CPUSVN,
+    /// which may not be higher than the enclave's own: an enclave can derive the keys of its
+    /// earlier versions, but not of later ones.
+    pub fn egetkey(&self, request: &Keyrequest) -> Result<[u8; 16], Box<dyn Error>> {
+        if request.keyname == Keyname::Report as u16 {
+            return report_key(&self.mrenclave);
+        }
+        if request.keyname != Keyname::Seal as u16 {
+            return Err(format!("Key name {} is not simulated", request.keyname).into());
+        }
+        if request.isvsvn > self.isvsvn {
+            return Err("Requested ISVSVN is higher than the enclave's".into());
+        }
+        if request
+            .cpusvn
+            .iter()
+            .zip(self.cpusvn.iter())
+            .any(|(requested, own)| requested > own)
+        {
+            return Err("Requested CPUSVN is higher than the platform's".into());
+        }
+
+        let mut data = SEAL_KEY_LABEL.to_vec();
+        data.extend(&request.keypolicy.bits().to_le_bytes());
+        if request.keypolicy.contains(Keypolicy::MRENCLAVE) {
+            data.extend(&self.mrenclave);
+        }
+        if request.keypolicy.contains(Keypolicy::MRSIGNER) {
+            data.extend(&self.mrsigner);
+        }
+        data.extend(&self.isvprodid.to_le_bytes());
+        data.extend(&request.isvsvn.to_le_bytes());
+        data.extend(&request.cpusvn);
+        data.extend(&(self.attributes.flags.bits() & request.attributemask[0]).to_le_bytes());
+        data.extend(&(self.attributes.xfrm & request.attributemask[1]).to_le_bytes());
+        data.extend(&(self.miscselect.bits() & request.miscmask).to_le_bytes());
+        data.extend(&request.keyid);
+        cmac(&PLATFORM_SECRET, &data)
+    }
+}
+
+/// This checks, as the target enclave identified by mrenclave would, that the Report was
+/// targeted at it and has not been altered.
+pub fn verify_report(report: &Report, mrenclave: &[u8; 32]) -> Result<(), Box<dyn Error>> {
+    if mac(report, mrenclave)? != report.mac {
+        return Err("Report was not targeted at this enclave, or its MAC is invalid".into());
+    }
+    Ok(())
+}
+
+/// The MAC of a Report is the AES-128-CMAC of its body under the target's REPORT key.
+fn mac(report: &Report, target_mrenclave: &[u8; 32]) -> Result<[u8; 16], Box<dyn Error>> {
+    cmac(
+        &report_key(target_mrenclave)?,
+        &report.as_ref()[..REPORT_BODY_SIZE],
+    )
+}
+
+/// The simulated REPORT key is the CMAC of the enclave's MRENCLAVE under the platform's
+/// secret.
+fn report_key(mrenclave: &[u8; 32]) -> Result<[u8; 16], Box<dyn Error>> {
+    cmac(&PLATFORM_SECRET, mrenclave)
+}
+
+fn cmac(key: &[u8; 16], data: &[u8]) -> Result<[u8; 16], Box<dyn Error>> {
+    let key = PKey::cmac(&Cipher::aes_128_cbc(), key)?;
+    let mut signer = Signer::new_without_digest(&key)?;
+    signer.update(data)?;
+
+    let mut mac = [0u8; 16];
+    mac.copy_from_slice(&signer.sign_to_vec()?);
+    Ok(mac)
+}
@@ -0,0 +1,53 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const DAY: i64 = 24 * 60 * 60;
+
+/// The current time, in seconds since the Unix epoch.
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+/// The time in the ISO 8601 format of Intel's collateral, e.g. 2019-08-01T10:18:20Z.
+pub fn iso8601(time: i64) -> String {
+    let (year, month, day, hour, min, sec) = civil(time);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year, month, day, hour, min, sec
+    )
+}
+
+/// The time in the format of an ASN.1 UTCTime, e.g. 190801101820Z.
+pub fn utc_time(time: i64) -> String {
+    let (year, month, day, hour, min, sec) = civil(time);
+    format!(
+        "{:02}{:02}{:02}{:02}{:02}{:02}Z",
+        year % 100,
+        month,
+        day,
+        hour,
+        min,
+        sec
+    )
+}
+
+/// This converts a Unix time into a UTC date and time. For the algorithm, you may refer to:
+/// http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil(time: i64) -> (i64, i64, i64, i64, i64, i64) {
+    let days = time.div_euclid(DAY);
+    let secs = time.rem_euclid(DAY);
+
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day, secs / 3600, secs % 3600 / 60, secs % 60)
+}
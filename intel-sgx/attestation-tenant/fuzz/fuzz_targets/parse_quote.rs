@@ -0,0 +1,11 @@
+#![no_main]
+use attestation_tenant::{decode_quote, Quote};
+use libfuzzer_sys::fuzz_target;
+
+// The Quote is parsed both as it is received from the daemon and as it is read from a file.
+fuzz_target!(|data: &[u8]| {
+    let _ = Quote::parse(data);
+    if let Ok(quote) = decode_quote(data) {
+        let _ = Quote::parse(&quote);
+    }
+});
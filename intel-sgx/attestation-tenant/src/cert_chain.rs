@@ -30,15 +30,15 @@ impl CertChain {
     /// This checks that the CertChain's length is under the maximum allowed.
     pub fn len_ok(&self) -> Result<(), Box<dyn Error>> {
         if self.chain.len() > self.max_len {
-            panic!("Certificate chain length exceeds max allowable.");
+            return Err("Certificate chain length exceeds max allowable.".into());
         }
         Ok(())
     }
 
     /// This returns the root certificate from the CertChain and mutates the CertChain.
     /// This is used in the chain's signature verification to add the root to the trusted store.
-    pub fn pop_root(&mut self) -> X509 {
-        self.chain.pop().unwrap()
+    pub fn pop_root(&mut self) -> Option<X509> {
+        self.chain.pop()
     }
 
     /// For all certificates in the CertChain, this verifies that the cert's issuer
@@ -51,10 +51,9 @@ impl CertChain {
                 continue;
             };
             if parent.unwrap().issued(&cert) != X509VerifyResult::OK {
-                panic!("Invalid issuer relationship in certificate chain.");
+                return Err("Invalid issuer relationship in certificate chain.".into());
             }
         }
-        println!("Issuer relationships in PCK cert chain are valid...");
         Ok(())
     }
 
@@ -63,7 +62,7 @@ impl CertChain {
     pub fn verify_sigs(mut self) -> Result<(), Box<dyn Error>> {
         // Parse out root cert, which will be at end of chain
         // The rest of the chain holds intermediate certs
-        let root_cert = self.pop_root();
+        let root_cert = self.pop_root().ok_or("Certificate chain is empty.")?;
 
         // Only the root certificate is added to the trusted store.
         let mut store_bldr = store::X509StoreBuilder::new()?;
@@ -83,9 +82,18 @@ impl CertChain {
         // This operation verifies the leaf (PCK_cert) in the context of the
         // chain. If the chain cannot be verified, the leaf will not be
         // verified.
-        context.init(&store, &self.leaf, &chain, |c| c.verify_cert())?;
-
-        println!("Signatures on certificate chain are valid...");
+        let result = context.init(&store, &self.leaf, &chain, |c| {
+            c.verify_cert().map(|verified| {
+                if verified {
+                    X509VerifyResult::OK
+                } else {
+                    c.error()
+                }
+            })
+        })?;
+        if result != X509VerifyResult::OK {
+            return Err(format!("PCK cert chain is invalid: {}", result.error_string()).into());
+        }
         Ok(())
     }
 }
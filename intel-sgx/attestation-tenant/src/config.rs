@@ -0,0 +1,63 @@
+use attestation_protocol::transport::{Address, Timeouts};
+use serde::Deserialize;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+const DAEMON_CONN: &'static str = "localhost:1034";
+const ENCLAVE_RA_TLS_CONN: &'static str = "localhost:1036";
+
+/// The timeouts used unless configured otherwise, so that a stalled daemon or enclave does
+/// not keep the tenant waiting for ever.
+const TIMEOUTS: Timeouts = Timeouts {
+    connect: Some(Duration::from_secs(10)),
+    read: Some(Duration::from_secs(60)),
+    write: Some(Duration::from_secs(60)),
+};
+
+/// The tenant's configuration, read from the TOML file given with `--config`, e.g.:
+///
+/// ```toml
+/// daemon = "sgx-host.example.com:1034"
+/// enclave_ra_tls = "sgx-host.example.com:1036"
+///
+/// [timeouts]
+/// connect = 5
+/// read = 30
+/// write = 30
+/// ```
+///
+/// Every setting is optional, and can be overridden on the command line. The timeouts
+/// default to 10 seconds to connect, and 60 seconds to read or write.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The address of the platform's attestation daemon.
+    pub daemon: Address,
+
+    /// The address of the enclave's RA-TLS server, with `--ra-tls`.
+    pub enclave_ra_tls: Address,
+
+    /// The timeouts of the tenant's connections. Timeouts that are not configured keep
+    /// their defaults.
+    pub timeouts: Timeouts,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            daemon: Address::Tcp(DAEMON_CONN.to_string()),
+            enclave_ra_tls: Address::Tcp(ENCLAVE_RA_TLS_CONN.to_string()),
+            timeouts: TIMEOUTS,
+        }
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let mut config: Config = toml::from_str(&fs::read_to_string(path)?)?;
+        config.timeouts = TIMEOUTS.overridden_by(config.timeouts);
+        Ok(config)
+    }
+}
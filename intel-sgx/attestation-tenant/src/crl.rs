@@ -0,0 +1,112 @@
+use openssl::{
+    asn1::Asn1Time,
+    nid::Nid,
+    x509::{CrlStatus, X509Crl, X509NameRef, X509},
+};
+use std::error::Error;
+
+/// This loads a CRL, which may be PEM or DER encoded. Intel serves the Root CA CRL in DER
+/// form and the PCK CA CRLs in either form.
+pub fn load(crl: &[u8]) -> Result<X509Crl, Box<dyn Error>> {
+    match X509Crl::from_pem(crl) {
+        Ok(crl) => Ok(crl),
+        Err(_) => Ok(X509Crl::from_der(crl)?),
+    }
+}
+
+/// This checks that none of the certificates in a chain (e.g. the PCK cert chain), nor its
+/// leaf certificate (e.g. the Quote's PCK leaf certificate), is revoked. The root is the
+/// tenant's trust anchor and is not checked.
+///
+/// The CRLs issued by a certificate of the chain must carry a valid signature and be
+/// current; the others are not relevant to the chain and are ignored. Every certificate
+/// checked must be covered by a CRL from its issuer: a missing CRL fails the check, since
+/// revocation cannot be ruled out without it.
+pub fn check_revocation(
+    chain: &[X509],
+    leaf: &X509,
+    crls: &[X509Crl],
+) -> Result<(), Box<dyn Error>> {
+    let now = Asn1Time::days_from_now(0)?;
+
+    for crl in crls {
+        let issuer = match find_subject(chain, crl.issuer_name())? {
+            Some(issuer) => issuer,
+            None => continue,
+        };
+
+        let issuer_key = issuer.public_key()?;
+        if !crl.verify(&issuer_key)? {
+            return Err(format!(
+                "CRL issued by {} has an invalid signature",
+                common_name(crl.issuer_name())
+            )
+            .into());
+        }
+
+        let current = match crl.next_update() {
+            Some(next_update) => crl.last_update() <= now && next_update >= now,
+            None => false,
+        };
+        if !current {
+            return Err(format!(
+                "CRL issued by {} is not current",
+                common_name(crl.issuer_name())
+            )
+            .into());
+        }
+    }
+
+    let (_root, intermediates) = chain.split_last().ok_or("certificate chain is empty")?;
+    for cert in intermediates.iter().chain(std::iter::once(leaf)) {
+        let issuer_name = name_der(cert.issuer_name())?;
+        let mut crl = None;
+        for candidate in crls {
+            if name_der(candidate.issuer_name())? == issuer_name {
+                crl = Some(candidate);
+                break;
+            }
+        }
+
+        let crl = crl.ok_or_else(|| {
+            format!(
+                "no CRL from {} was supplied",
+                common_name(cert.issuer_name())
+            )
+        })?;
+        if let CrlStatus::Revoked(_) = crl.get_by_cert(cert) {
+            return Err(format!(
+                "certificate {} is revoked",
+                common_name(cert.subject_name())
+            )
+            .into());
+        }
+    }
+
+    Ok(())
+}
+
+/// This finds the certificate of the chain whose subject is the given name.
+fn find_subject<'a>(
+    chain: &'a [X509],
+    name: &X509NameRef,
+) -> Result<Option<&'a X509>, Box<dyn Error>> {
+    let name = name_der(name)?;
+    for cert in chain {
+        if name_der(cert.subject_name())? == name {
+            return Ok(Some(cert));
+        }
+    }
+    Ok(None)
+}
+
+fn name_der(name: &X509NameRef) -> Result<Vec<u8>, Box<dyn Error>> {
+    Ok(name.to_der()?)
+}
+
+fn common_name(name: &X509NameRef) -> String {
+    name.entries_by_nid(Nid::COMMONNAME)
+        .next()
+        .map(|entry| String::from_utf8_lossy(entry.data().as_slice()).into_owned())
+        .unwrap_or_else(|| "<unnamed>".to_string())
+}
@@ -0,0 +1,139 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+pub const BOOLEAN: u8 = 0x01;
+pub const INTEGER: u8 = 0x02;
+pub const OCTET_STRING: u8 = 0x04;
+pub const OBJECT_IDENTIFIER: u8 = 0x06;
+pub const ENUMERATED: u8 = 0x0a;
+pub const SEQUENCE: u8 = 0x30;
+
+/// The context-specific tags of a certificate's optional version and extensions fields.
+const TBS_VERSION: u8 = 0xa0;
+const TBS_EXTENSIONS: u8 = 0xa3;
+
+/// This is the error returned when DER-encoded data is malformed.
+#[derive(Debug, Clone)]
+pub struct DerError(pub &'static str);
+
+impl Error for DerError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+}
+
+impl Display for DerError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "malformed DER: {}", self.0)
+    }
+}
+
+/// A minimal reader of DER-encoded data, which walks a sequence of tag-length-value
+/// elements. It only supports the single-byte tags and definite lengths used in X.509.
+pub struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Reader { data }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn peek_tag(&self) -> Option<u8> {
+        self.data.first().cloned()
+    }
+
+    /// This reads the next element, returning its tag and its contents.
+    pub fn read_any(&mut self) -> Result<(u8, &'a [u8]), DerError> {
+        let (&tag, rest) = self.data.split_first().ok_or(DerError("truncated tag"))?;
+        let (&first, rest) = rest.split_first().ok_or(DerError("truncated length"))?;
+
+        let (len, rest) = if first < 0x80 {
+            (first as usize, rest)
+        } else {
+            let count = (first & 0x7f) as usize;
+            if count == 0 || count > 4 || rest.len() < count {
+                return Err(DerError("invalid length"));
+            }
+            let len = rest[..count]
+                .iter()
+                .fold(0usize, |len, &b| (len << 8) | b as usize);
+            (len, &rest[count..])
+        };
+
+        if rest.len() < len {
+            return Err(DerError("truncated contents"));
+        }
+        self.data = &rest[len..];
+        Ok((tag, &rest[..len]))
+    }
+
+    /// This reads the next element, which must have the given tag, and returns its contents.
+    pub fn read(&mut self, tag: u8) -> Result<&'a [u8], DerError> {
+        match self.read_any()? {
+            (t, contents) if t == tag => Ok(contents),
+            _ => Err(DerError("unexpected tag")),
+        }
+    }
+
+    /// This reads the next element if it has the given tag.
+    pub fn read_optional(&mut self, tag: u8) -> Result<Option<&'a [u8]>, DerError> {
+        if self.peek_tag() == Some(tag) {
+            self.read(tag).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// This decodes the contents of a non-negative INTEGER or ENUMERATED that fits in a u64.
+pub fn unsigned(contents: &[u8]) -> Result<u64, DerError> {
+    let contents = match contents.split_first() {
+        Some((0, rest)) if !rest.is_empty() => rest,
+        Some((b, _)) if b & 0x80 != 0 => return Err(DerError("negative integer")),
+        Some(_) => contents,
+        None => return Err(DerError("empty integer")),
+    };
+    if contents.len() > 8 {
+        return Err(DerError("integer too large"));
+    }
+    Ok(contents.iter().fold(0u64, |n, &b| (n << 8) | u64::from(b)))
+}
+
+/// This finds the extension with the given (encoded) OID in a DER-encoded certificate, and
+/// returns the contents of its extnValue.
+pub fn find_extension<'a>(cert: &'a [u8], oid: &[u8]) -> Result<Option<&'a [u8]>, DerError> {
+    let mut cert = Reader::new(Reader::new(cert).read(SEQUENCE)?);
+    let mut tbs = Reader::new(cert.read(SEQUENCE)?);
+
+    // The serial number, signature algorithm, issuer, validity, subject and subject public
+    // key info precede the optional unique IDs and extensions.
+    tbs.read_optional(TBS_VERSION)?;
+    for _ in 0..6 {
+        tbs.read_any()?;
+    }
+
+    while !tbs.is_empty() {
+        let (tag, contents) = tbs.read_any()?;
+        if tag != TBS_EXTENSIONS {
+            continue;
+        }
+
+        let mut extensions = Reader::new(Reader::new(contents).read(SEQUENCE)?);
+        while !extensions.is_empty() {
+            let mut extension = Reader::new(extensions.read(SEQUENCE)?);
+            let extn_id = extension.read(OBJECT_IDENTIFIER)?;
+            extension.read_optional(BOOLEAN)?;
+            let extn_value = extension.read(OCTET_STRING)?;
+            if extn_id == oid {
+                return Ok(Some(extn_value));
+            }
+        }
+    }
+
+    Ok(None)
+}
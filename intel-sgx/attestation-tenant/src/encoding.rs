@@ -0,0 +1,23 @@
+use std::error::Error;
+
+/// This decodes a Quote read from a file. The file may hold the Quote encoded as hex or
+/// base64 text (surrounding whitespace and line breaks are ignored), or the raw binary Quote.
+/// A raw Quote starts with its little-endian version number, whose zero byte is valid in
+/// neither encoding, so it is never mistaken for text.
+pub fn decode_quote(contents: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    if contents.is_empty() {
+        return Err("Quote file is empty.".into());
+    }
+
+    if let Ok(text) = std::str::from_utf8(contents) {
+        let text: String = text.split_whitespace().collect();
+        if let Ok(quote) = hex::decode(&text) {
+            return Ok(quote);
+        }
+        if let Ok(quote) = base64::decode(&text) {
+            return Ok(quote);
+        }
+    }
+
+    Ok(contents.to_vec())
+}
@@ -0,0 +1,172 @@
+use crate::tcb::TcbStatus;
+use serde::Deserialize;
+use sgx_isa::{AttributesFlags, Report};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+/// The size of a Report without its padding. The Quote only carries the Report's
+/// body (the first 384 bytes); the KEYID and MAC fields are left zeroed.
+const REPORT_SIZE: usize = 432;
+
+/// This parses a Report body, as embedded in a Quote, into an sgx_isa::Report.
+pub fn report_from_body(body: &[u8]) -> Option<Report> {
+    const BODY_SIZE: usize = 384;
+    if body.len() != BODY_SIZE {
+        return None;
+    }
+
+    let mut buf = [0u8; REPORT_SIZE];
+    buf[..BODY_SIZE].copy_from_slice(body);
+    Report::try_copy_from(&buf)
+}
+
+/// This is the error returned when the enclave's identity does not satisfy the policy.
+#[derive(Debug, Clone)]
+pub enum IdentityError {
+    Mrenclave([u8; 32]),
+    Mrsigner([u8; 32]),
+    IsvProdId { expected: u16, found: u16 },
+    IsvSvn { minimum: u16, found: u16 },
+    Debug,
+}
+
+impl Error for IdentityError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+}
+
+impl Display for IdentityError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            IdentityError::Mrenclave(m) => write!(f, "MRENCLAVE {} is not allowed", hex::encode(m)),
+            IdentityError::Mrsigner(m) => write!(f, "MRSIGNER {} is not allowed", hex::encode(m)),
+            IdentityError::IsvProdId { expected, found } => {
+                write!(f, "ISVPRODID is {}, expected {}", found, expected)
+            }
+            IdentityError::IsvSvn { minimum, found } => {
+                write!(f, "ISVSVN is {}, the minimum is {}", found, minimum)
+            }
+            IdentityError::Debug => write!(f, "enclave is a DEBUG enclave"),
+        }
+    }
+}
+
+/// The identity policy as it appears in the tenant's JSON policy file. Measurements are
+/// hex-encoded. Unknown keys are rejected, so that a misspelled one does not silently weaken
+/// the policy.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PolicyFile {
+    #[serde(default)]
+    mrenclave: Vec<String>,
+    #[serde(default)]
+    mrsigner: Vec<String>,
+    isv_prod_id: Option<u16>,
+    #[serde(default)]
+    min_isv_svn: u16,
+    #[serde(default)]
+    allow_debug: bool,
+    #[serde(default = "default_tcb_statuses")]
+    allowed_tcb_statuses: Vec<TcbStatus>,
+}
+
+fn default_tcb_statuses() -> Vec<TcbStatus> {
+    vec![TcbStatus::UpToDate]
+}
+
+/// This describes which enclaves the tenant is willing to trust. An empty allow-list
+/// accepts any value, but at least one of MRENCLAVE and MRSIGNER must be pinned. The
+/// platform's TCB status must be one of allowed_tcb_statuses, which defaults to UpToDate.
+#[derive(Debug, Clone)]
+pub struct Policy {
+    pub mrenclave: Vec<[u8; 32]>,
+    pub mrsigner: Vec<[u8; 32]>,
+    pub isv_prod_id: Option<u16>,
+    pub min_isv_svn: u16,
+    pub allow_debug: bool,
+    pub allowed_tcb_statuses: Vec<TcbStatus>,
+}
+
+impl Policy {
+    /// This creates a policy that allows the given MRENCLAVEs and MRSIGNERs, of which there
+    /// must be at least one. Like the policy file, it allows any ISVPRODID and ISVSVN, no
+    /// DEBUG enclaves, and only platforms that are UpToDate.
+    pub fn new(mrenclave: Vec<[u8; 32]>, mrsigner: Vec<[u8; 32]>) -> Result<Self, Box<dyn Error>> {
+        if mrenclave.is_empty() && mrsigner.is_empty() {
+            return Err("Identity policy must allow at least one MRENCLAVE or MRSIGNER.".into());
+        }
+        Ok(Policy {
+            mrenclave,
+            mrsigner,
+            isv_prod_id: None,
+            min_isv_svn: 0,
+            allow_debug: false,
+            allowed_tcb_statuses: default_tcb_statuses(),
+        })
+    }
+
+    /// The Policy is parsed from the contents of the tenant's JSON policy file, e.g.:
+    /// `{ "mrsigner": ["<hex>"], "isv_prod_id": 0, "min_isv_svn": 1, "allow_debug": false,
+    ///    "allowed_tcb_statuses": ["UpToDate"] }`
+    pub fn from_json(json: &str) -> Result<Self, Box<dyn Error>> {
+        let file: PolicyFile = serde_json::from_str(json)?;
+        Ok(Policy {
+            isv_prod_id: file.isv_prod_id,
+            min_isv_svn: file.min_isv_svn,
+            allow_debug: file.allow_debug,
+            allowed_tcb_statuses: file.allowed_tcb_statuses,
+            ..Policy::new(
+                decode_measurements(&file.mrenclave)?,
+                decode_measurements(&file.mrsigner)?,
+            )?
+        })
+    }
+
+    /// This checks the attested enclave's Report against the policy.
+    pub fn appraise(&self, report: &Report) -> Result<(), IdentityError> {
+        if !self.mrenclave.is_empty() && !self.mrenclave.contains(&report.mrenclave) {
+            return Err(IdentityError::Mrenclave(report.mrenclave));
+        }
+
+        if !self.mrsigner.is_empty() && !self.mrsigner.contains(&report.mrsigner) {
+            return Err(IdentityError::Mrsigner(report.mrsigner));
+        }
+
+        if let Some(expected) = self.isv_prod_id {
+            if report.isvprodid != expected {
+                return Err(IdentityError::IsvProdId {
+                    expected,
+                    found: report.isvprodid,
+                });
+            }
+        }
+
+        if report.isvsvn < self.min_isv_svn {
+            return Err(IdentityError::IsvSvn {
+                minimum: self.min_isv_svn,
+                found: report.isvsvn,
+            });
+        }
+
+        if !self.allow_debug && report.attributes.flags.contains(AttributesFlags::DEBUG) {
+            return Err(IdentityError::Debug);
+        }
+
+        Ok(())
+    }
+}
+
+fn decode_measurements(hex_values: &[String]) -> Result<Vec<[u8; 32]>, Box<dyn Error>> {
+    let mut measurements = Vec::new();
+    for value in hex_values {
+        let bytes = hex::decode(value.trim())?;
+        if bytes.len() != 32 {
+            return Err(format!("Measurement {} is not 32 bytes long.", value).into());
+        }
+        let mut m = [0u8; 32];
+        m.copy_from_slice(&bytes);
+        measurements.push(m);
+    }
+    Ok(measurements)
+}
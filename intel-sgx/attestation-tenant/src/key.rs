@@ -36,6 +36,9 @@ pub struct Key {
 impl Key {
     /// This creates a new Key from raw x and y coordinates for the SECP256R1 curve.
     pub fn new_from_xy(xy_coords: &[u8]) -> Result<Self, Box<dyn Error>> {
+        if xy_coords.len() != 64 {
+            return Err(format!("Key must be 64 bytes long, not {}.", xy_coords.len()).into());
+        }
         let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?;
         let mut x: [u8; 32] = Default::default();
         let mut y: [u8; 32] = Default::default();
@@ -59,7 +62,9 @@ impl Key {
     pub fn verify_sig(&self, signed: &[u8], sig: &Vec<u8>) -> Result<(), Box<dyn Error>> {
         let mut verifier = Verifier::new(MessageDigest::sha256(), &self.pkey)?;
         verifier.update(signed)?;
-        verifier.verify(sig)?;
+        if !verifier.verify(sig)? {
+            return Err("Signature could not be verified.".into());
+        }
         Ok(())
     }
 
@@ -0,0 +1,28 @@
+//! The tenant's Quote verification, usable in-process by services that need to verify
+//! Quotes as well as by the `attestation-tenant` binary.
+
+mod cert_chain;
+mod crl;
+mod der;
+mod encoding;
+pub mod identity;
+mod key;
+mod qe_identity;
+mod quote;
+mod ra_tls;
+mod session;
+mod sgx_ext;
+mod sig;
+mod signed;
+mod tcb;
+mod verifier;
+
+pub use encoding::decode_quote;
+pub use identity::{IdentityError, Policy};
+pub use qe_identity::QeIdentity;
+pub use quote::{Quote, QuoteError};
+pub use ra_tls::RaTlsVerifier;
+pub use session::{Attestation, EnclaveSession};
+pub use sgx_ext::{SgxExtension, SgxType, Tcb};
+pub use tcb::{TcbEvaluation, TcbInfo, TcbStatus};
+pub use verifier::{Check, Collateral, Identity, Outcome, QuoteVerifier, Verification};
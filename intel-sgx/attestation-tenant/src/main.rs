@@ -1,115 +1,267 @@
-mod cert_chain;
-mod key;
-mod sig;
-
-use bufstream::BufStream;
-use dcap_ql::quote::{Qe3CertDataPckCertChain, Quote3SignatureEcdsaP256};
-use openssl::x509::*;
-use std::{
-    borrow::Borrow,
-    convert::TryFrom,
-    env,
-    error::Error,
-    fs,
-    io::{Read, Write},
-    iter::Iterator,
-    net::TcpStream,
-};
+mod config;
 
-const DAEMON_CONN: &'static str = "localhost:1034";
+use attestation_protocol::transport::{self, Stream, Timeouts};
+use attestation_tenant::{
+    decode_quote, Attestation, Collateral, Policy, QuoteVerifier, RaTlsVerifier, Verification,
+};
+use config::Config;
+use rustls::{ClientConfig, ClientConnection, ServerName, StreamOwned};
+use std::convert::TryFrom;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::{env, error::Error, fs};
 
 /// The tenant requests attestation of an enclave from the platform's attestation daemon, and
 /// receives a Quote from the daemon. The Quote verifies the enclave's measurement. The tenant
-/// verifies:
-/// 1. That the Quote's PCK Certificate (embedded in the Cert Data) is valid.
-/// 2. That the PCK Certificate's Key signed the platform's Attestation Key.
-/// 3. That the Attestation Key signed the Quote.
-/// 4. That the hashed material (containing the Attestation Key) signed by the PCK is valid.
+/// verifies the Quote with a QuoteVerifier, which also checks that the enclave's Report binds
+/// the nonce sent by the tenant, i.e. that the Quote is fresh. Once the Quote is verified,
+/// the tenant opens a secure channel with the enclave, using the ephemeral public key the
+/// enclave bound into its Report.
 ///
-/// For more information on Intel's PCK and certificate chains, you may refer to:
-/// https://download.01.org/intel-sgx/dcap-1.0/docs/SGX_PCK_Certificate_CRL_Spec-1.0.pdf
+/// Each `--secret NAME=FILE` supplies a secret, which the tenant releases to the enclave
+/// over the secure channel once the Quote is verified, and only then. The enclave
+/// acknowledges every secret it receives.
 ///
-/// For more informtation on Intel's Attestation Key and the Quote, you may refer to:
-/// https://download.01.org/intel-sgx/dcap-1.0/docs/SGX_ECDSA_QuoteGenReference_DCAP_API_Linux_1.0.pdf
-
+/// With `--quote FILE`, the tenant verifies a previously captured Quote (raw binary, hex or
+/// base64) instead of requesting one. No nonce is involved, so such a Quote's freshness is
+/// not checked.
+///
+/// With `--ra-tls`, the tenant instead connects over TLS to the enclave's RA-TLS server, and
+/// verifies the Quote in the enclave's certificate during the handshake.
+///
+/// Each `--crl FILE` supplies a CRL issued by a CA of the PCK cert chain. The Root CA CRL and
+/// the CRL of the CA that issued the PCK Certificate are required.
+///
+/// `--tcb-info FILE` and `--tcb-chain FILE` supply Intel's TCB Info for the platform's FMSPC
+/// and the chain of the key that signed it. They are required to evaluate the platform's
+/// TCB status. `--qe-identity FILE` supplies Intel's QE Identity, which is signed by the same
+/// key, and is required to check that the Quote comes from the genuine Quoting Enclave.
+///
+/// The addresses of the daemon and of the enclave's RA-TLS server, and the timeouts, are
+/// read from the TOML file given with `--config FILE` (see `Config`), and can be overridden
+/// with `--daemon ADDR`, `--enclave-ra-tls ADDR`, `--connect-timeout SECS`,
+/// `--read-timeout SECS` and `--write-timeout SECS`. Addresses are TCP (`HOST:PORT`), Unix
+/// domain socket (`unix:PATH`) or vsock (`vsock:CID:PORT`) addresses.
 fn main() -> Result<(), Box<dyn Error>> {
+    let mut args = env::args().skip(1);
+    let mut positional = Vec::new();
+    let mut quote_file = None;
+    let mut ra_tls = false;
+    let mut secrets = Vec::new();
+    let mut crl_files = Vec::new();
+    let mut tcb_info_file = None;
+    let mut tcb_chain_file = None;
+    let mut qe_identity_file = None;
+    let mut config_file = None;
+    let mut daemon = None;
+    let mut enclave_ra_tls = None;
+    let mut timeouts = Timeouts::default();
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--quote" => {
+                quote_file = Some(
+                    args.next()
+                        .expect("--quote requires the path of a Quote file."),
+                )
+            }
+            "--ra-tls" => ra_tls = true,
+            "--secret" => {
+                let secret = args
+                    .next()
+                    .expect("--secret requires a name and the path of a secret file.");
+                let mut secret = secret.splitn(2, '=');
+                match (secret.next(), secret.next()) {
+                    (Some(name), Some(file)) => secrets.push((name.to_string(), file.to_string())),
+                    _ => panic!(
+                        "--secret requires a name and the path of a secret file, as NAME=FILE."
+                    ),
+                }
+            }
+            "--crl" => crl_files.push(args.next().expect("--crl requires the path of a CRL file.")),
+            "--tcb-info" => {
+                tcb_info_file = Some(
+                    args.next()
+                        .expect("--tcb-info requires the path of a TCB Info file."),
+                )
+            }
+            "--tcb-chain" => {
+                tcb_chain_file = Some(
+                    args.next()
+                        .expect("--tcb-chain requires the path of the TCB signing chain."),
+                )
+            }
+            "--qe-identity" => {
+                qe_identity_file = Some(
+                    args.next()
+                        .expect("--qe-identity requires the path of a QE Identity file."),
+                )
+            }
+            "--config" => {
+                config_file = Some(
+                    args.next()
+                        .expect("--config requires the path of a configuration file."),
+                )
+            }
+            "--daemon" => {
+                daemon = Some(
+                    args.next()
+                        .expect("--daemon requires an address.")
+                        .parse()?,
+                )
+            }
+            "--enclave-ra-tls" => {
+                enclave_ra_tls = Some(
+                    args.next()
+                        .expect("--enclave-ra-tls requires an address.")
+                        .parse()?,
+                )
+            }
+            "--connect-timeout" | "--read-timeout" | "--write-timeout" => {
+                let timeout = Some(transport::parse_timeout(
+                    &args.next().expect("Timeouts require a number of seconds."),
+                )?);
+                match &arg[..] {
+                    "--connect-timeout" => timeouts.connect = timeout,
+                    "--read-timeout" => timeouts.read = timeout,
+                    _ => timeouts.write = timeout,
+                }
+            }
+            _ => positional.push(arg),
+        }
+    }
+
+    let mut config = match config_file {
+        Some(path) => Config::load(Path::new(&path))?,
+        None => Config::default(),
+    };
+    config.daemon = daemon.unwrap_or(config.daemon);
+    config.enclave_ra_tls = enclave_ra_tls.unwrap_or(config.enclave_ra_tls);
+    config.timeouts = config.timeouts.overridden_by(timeouts);
+
     // The tenant's PCK certificate chain must be loaded to verify the Quote's PCK Leaf
     // Certificate. The root certificate in this chain is trusted, since it is provided by the
     // tenant.
-    let cert_chain_file = env::args()
-        .nth(1)
+    let cert_chain_file = positional
+        .first()
         .expect("You must supply the path of a valid PCK certificate chain as the first argument.");
-    let cert_chain_file_contents =
-        fs::read_to_string(&cert_chain_file[..]).expect("PCK cert chain file path invalid.");
-
-    // The tenant requests attestation from the platform's attestation daemon.
-    // The actual signal is arbitrary.
-    let daemon_conn = TcpStream::connect(DAEMON_CONN)?;
-    let mut daemon_buf = BufStream::new(daemon_conn);
-    daemon_buf.write(&b"Request attestation"[..])?;
-
-    // The tenant receives a Quote from the platform's attestation
-    // daemon. This Quote verifies the enclave's self-measurement from its Report.
-    let mut quote = Vec::new();
-    daemon_buf.read_to_end(&mut quote)?;
-
-    // The signed material for the Quoting Enclave's Attestation Key (Quote Header ||
-    // ISV Enclave Report) is retrieved.
-    let att_key_signed_material = dcap_ql::quote::Quote::raw_header_and_body(&quote)?;
-
-    // The hashed material (containing the Attestation Key) signed by the PCK is retrieved.
-    let hashed_reportdata = dcap_ql::quote::Quote::raw_pck_hash(&quote)?;
-
-    // This parses the Quote's signature section.
-    let quote = dcap_ql::quote::Quote::parse(&quote)?;
-    let q_sig = quote.signature::<Quote3SignatureEcdsaP256>()?;
-    let q_enclave_report_sig = q_sig.signature();
-    let q_qe_report = q_sig.qe3_report();
-    let q_qe_report_sig = q_sig.qe3_signature();
-    let q_att_key_pub = q_sig.attestation_public_key();
-    let q_auth_data = q_sig.authentication_data();
-
-    // The Quote's Certification Data contains the PCK Cert Chain and PCK Certificate;
-    // the embedded PCK signs the Attestation Key.
-    let cert_data = q_sig.certification_data::<Qe3CertDataPckCertChain>()?;
-    let quote_pck_leaf_cert = cert_data.leaf_cert;
-
-    // The PCK chain is reconstructed with the Quote's leaf cert added to end of tenant's chain.
-    let cert_chain = cert_chain::CertChain::new_from_chain(
-        X509::stack_from_pem(cert_chain_file_contents.as_bytes())?,
-        &quote_pck_leaf_cert,
-    );
-    cert_chain.len_ok()?;
+    let mut collateral = Collateral::from_pem(
+        &fs::read(&cert_chain_file[..]).expect("PCK cert chain file path invalid."),
+    )?;
     println!("Tenant's PCK cert chain loaded...");
 
-    // The PCK certificate chain's issuers and signatures are verified.
-    cert_chain.verify_issuers()?;
-    cert_chain.verify_sigs()?;
-    println!("PCK cert chain verified...");
-
-    // The Attestation Key's signature on the Quote is verified.
-    let attestation_key = key::Key::new_from_xy(&q_att_key_pub)?;
-    let quote_signature = sig::Signature::try_from(q_enclave_report_sig)?.to_der_vec()?;
-    attestation_key.verify_sig(&att_key_signed_material, &quote_signature)?;
-    println!("AK signature on Quote header || report body is valid...");
-
-    // The PCK's signature on the Attestation Public Key is verified.
-    let pc_key = key::Key::new_from_pubkey(quote_pck_leaf_cert.public_key()?);
-    let qe_report_signature = sig::Signature::try_from(q_qe_report_sig)?.to_der_vec()?;
-    pc_key
-        .borrow()
-        .verify_sig(&q_qe_report, &qe_report_signature)?;
-    println!("PCK signature on AK is valid...");
-
-    // This verifies that the hashed material signed by the PCK is correct.
-    let mut unhashed_data = Vec::new();
-    unhashed_data.extend(q_att_key_pub.to_vec());
-    unhashed_data.extend(q_auth_data.to_vec());
-    pc_key
-        .borrow()
-        .verify_hash(hashed_reportdata, unhashed_data)?;
-    println!("QE Report's hash is valid....");
+    // The CRLs issued by the chain's CAs are needed to rule out that the PCK Certificate,
+    // or a CA above it, was revoked.
+    for crl_file in &crl_files {
+        collateral.add_crl(&fs::read(&crl_file[..]).expect("CRL file path invalid."))?;
+    }
+
+    // The TCB Info for the platform's FMSPC tells whether the platform's TCB is current, and
+    // the QE Identity tells which enclave is the genuine Quoting Enclave. Both are signed
+    // with the TCB Signing Key.
+    if tcb_info_file.is_some() || qe_identity_file.is_some() {
+        let tcb_chain_file = tcb_chain_file
+            .expect("--tcb-info and --qe-identity require --tcb-chain, the TCB signing chain.");
+        let tcb_chain =
+            fs::read(&tcb_chain_file[..]).expect("TCB signing chain file path invalid.");
+
+        if let Some(tcb_info_file) = tcb_info_file {
+            collateral.set_tcb_info(
+                &fs::read_to_string(&tcb_info_file[..]).expect("TCB Info file path invalid."),
+                &tcb_chain,
+            )?;
+        }
+        if let Some(qe_identity_file) = qe_identity_file {
+            collateral.set_qe_identity(
+                &fs::read_to_string(&qe_identity_file[..]).expect("QE Identity file path invalid."),
+                &tcb_chain,
+            )?;
+        }
+    }
+
+    // The tenant's identity policy describes which enclaves it is willing to trust.
+    let policy_file = positional
+        .get(1)
+        .expect("You must supply the path of an identity policy file as the second argument.");
+    let policy = Policy::from_json(
+        &fs::read_to_string(&policy_file[..]).expect("Identity policy file path invalid."),
+    )?;
+
+    if ra_tls {
+        return connect_ra_tls(&config, collateral, policy);
+    }
 
+    if let Some(quote_file) = quote_file {
+        let quote = decode_quote(&fs::read(&quote_file[..]).expect("Quote file path invalid."))?;
+        println!(
+            "Verifying Quote from {}; its freshness is not checked...",
+            quote_file
+        );
+        return check(&QuoteVerifier::new().verify(&quote, &collateral, &policy)?);
+    }
+
+    // The Report must bind the tenant's nonce, together with the enclave's data.
+    let mut attestation = Attestation::request(Stream::connect(&config.daemon, &config.timeouts)?)?;
+    check(&attestation.verify(&collateral, &policy)?)?;
+
+    // The enclave is now trusted, and so is the public key it bound into its Report: only
+    // the attested enclave can read what the tenant sends over the channel, such as the
+    // tenant's secrets.
+    let mut session = attestation.open_channel()?;
+    println!("Secure channel with the enclave established.");
+    for (name, secret_file) in &secrets {
+        let secret = fs::read(&secret_file[..]).expect("Secret file path invalid.");
+        session.provision(name, &secret)?;
+        println!("Secret {} provisioned to the enclave.", name);
+    }
+    Ok(())
+}
+
+/// This prints the Verification, and fails unless the Quote was verified.
+fn check(verification: &Verification) -> Result<(), Box<dyn Error>> {
+    println!("{}", verification);
+
+    if !verification.verified() {
+        return Err("Quote could not be verified.".into());
+    }
     println!("\nQuote verified.");
     Ok(())
 }
+
+/// This connects to the enclave's RA-TLS server. The RaTlsVerifier verifies the Quote in
+/// the enclave's certificate during the handshake, which fails unless it is verified.
+fn connect_ra_tls(
+    config: &Config,
+    collateral: Collateral,
+    policy: Policy,
+) -> Result<(), Box<dyn Error>> {
+    let verifier = Arc::new(RaTlsVerifier::new(collateral, policy));
+    let client_config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(verifier.clone())
+        .with_no_client_auth();
+
+    // The enclave is identified by its Quote, not by its name, which is not checked.
+    let server_name = ServerName::try_from("localhost")?;
+    let connection = ClientConnection::new(Arc::new(client_config), server_name)?;
+    let stream = Stream::connect(&config.enclave_ra_tls, &config.timeouts)?;
+    let mut tls = StreamOwned::new(connection, stream);
+
+    // The handshake is completed by the first write. The Verification is printed whether
+    // or not the Quote was verified.
+    let greeting = b"Hello from the tenant.";
+    let handshake = tls.write_all(greeting);
+    if let Some(verification) = verifier.verification() {
+        println!("{}", verification);
+    }
+    handshake?;
+    println!("\nQuote verified.");
+
+    let mut echo = vec![0u8; greeting.len()];
+    tls.read_exact(&mut echo)?;
+    if echo != greeting {
+        return Err("The enclave did not echo the tenant's message.".into());
+    }
+    println!("RA-TLS connection with the enclave established.");
+    Ok(())
+}
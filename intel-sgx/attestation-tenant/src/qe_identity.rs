@@ -0,0 +1,112 @@
+use crate::{identity, signed::SignedJson, tcb::TcbStatus};
+use openssl::x509::{X509Crl, X509};
+use serde::Deserialize;
+use std::error::Error;
+
+/// The ATTRIBUTES field of a Report body.
+const REPORT_ATTRIBUTES: std::ops::Range<usize> = 48..64;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QeIdentityBody {
+    id: String,
+    version: u32,
+    issue_date: String,
+    next_update: String,
+    miscselect: String,
+    miscselect_mask: String,
+    attributes: String,
+    attributes_mask: String,
+    mrsigner: String,
+    isvprodid: u16,
+    tcb_levels: Vec<QeTcbLevel>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QeTcbLevel {
+    tcb: QeTcb,
+    tcb_status: TcbStatus,
+}
+
+#[derive(Deserialize)]
+struct QeTcb {
+    isvsvn: u16,
+}
+
+/// Intel's QE Identity, which describes the genuine Quoting Enclave: its MRSIGNER and
+/// ISVPRODID, the MISCSELECT and ATTRIBUTES it must have under the given masks, and the
+/// status of its TCB levels (ISVSVNs). It is signed by the TCB Signing Key. For more
+/// information, you may refer to:
+/// https://api.portal.trustedservices.intel.com/documentation#pcs-qe-identity
+pub struct QeIdentity {
+    body: QeIdentityBody,
+    signed: SignedJson,
+}
+
+impl QeIdentity {
+    /// The QeIdentity is parsed from the QE Identity JSON and the PEM-encoded TCB signing
+    /// chain (the TCB Signing Certificate, followed by the Root CA).
+    pub fn from_json(json: &str, signing_chain: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let signed =
+            SignedJson::from_json(json, &["qeIdentity", "enclaveIdentity"], signing_chain)?;
+        let body: QeIdentityBody = serde_json::from_str(signed.body())?;
+        if body.version != 2 {
+            return Err(format!("QE Identity version {} is not supported", body.version).into());
+        }
+        // Identities of other Intel enclaves, e.g. the QVE, are signed the same way.
+        if body.id != "QE" {
+            return Err(format!("{} Identity is not a QE Identity", body.id).into());
+        }
+        Ok(QeIdentity { body, signed })
+    }
+
+    /// This verifies that the QE Identity was signed by a TCB Signing Certificate issued by
+    /// the tenant's root, which is not revoked, and that the QE Identity is current.
+    pub fn verify(&self, root: &X509, crls: &[X509Crl]) -> Result<(), Box<dyn Error>> {
+        self.signed
+            .verify(root, crls, &self.body.issue_date, &self.body.next_update)
+            .map_err(|e| format!("QE Identity: {}", e).into())
+    }
+
+    /// This checks the Quoting Enclave's Report body against the QE Identity, and returns
+    /// the status of the Quoting Enclave's TCB level.
+    pub fn appraise(&self, qe_report: &[u8]) -> Result<TcbStatus, Box<dyn Error>> {
+        let report = identity::report_from_body(qe_report).ok_or("QE Report is malformed")?;
+
+        let miscselect = u32::from_str_radix(&self.body.miscselect, 16)?;
+        let miscselect_mask = u32::from_str_radix(&self.body.miscselect_mask, 16)?;
+        if report.miscselect.bits() & miscselect_mask != miscselect & miscselect_mask {
+            return Err("QE MISCSELECT does not match QE Identity".into());
+        }
+
+        let attributes = hex::decode(&self.body.attributes)?;
+        let attributes_mask = hex::decode(&self.body.attributes_mask)?;
+        if attributes.len() != 16 || attributes_mask.len() != 16 {
+            return Err("QE Identity attributes must be 16 bytes long".into());
+        }
+        let matches = qe_report[REPORT_ATTRIBUTES]
+            .iter()
+            .zip(&attributes)
+            .zip(&attributes_mask)
+            .all(|((r, a), m)| r & m == a & m);
+        if !matches {
+            return Err("QE ATTRIBUTES do not match QE Identity".into());
+        }
+
+        if hex::decode(&self.body.mrsigner)? != report.mrsigner {
+            return Err("QE MRSIGNER does not match QE Identity".into());
+        }
+        if report.isvprodid != self.body.isvprodid {
+            return Err("QE ISVPRODID does not match QE Identity".into());
+        }
+
+        // The TCB levels are sorted from the highest to the lowest ISVSVN.
+        self.body
+            .tcb_levels
+            .iter()
+            .find(|level| report.isvsvn >= level.tcb.isvsvn)
+            .map(|level| level.tcb_status)
+            .ok_or_else(|| "QE ISVSVN is lower than every TCB level in QE Identity".into())
+    }
+}
@@ -0,0 +1,190 @@
+use openssl::x509::X509;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+/// The only Quote version and Attestation Key type supported: a version 3 Quote signed
+/// with an ECDSA-256-with-P-256 Attestation Key.
+const QUOTE_VERSION: u16 = 3;
+const ECDSA_P256: u16 = 2;
+
+/// The Certification Data of the Quote's signature holds the PEM-encoded PCK cert chain.
+const CERT_DATA_PCK_CERT_CHAIN: u16 = 5;
+
+const HEADER_SIZE: usize = 48;
+const REPORT_BODY_SIZE: usize = 384;
+const SIGNATURE_SIZE: usize = 64;
+const PUBLIC_KEY_SIZE: usize = 64;
+
+/// This is the error returned when a Quote is malformed or of an unsupported type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QuoteError {
+    /// The Quote ends in the middle of the given field.
+    Truncated(&'static str),
+    Version(u16),
+    AttestationKeyType(u16),
+    CertDataType(u16),
+
+    /// The Quote, or its Signature Data, has this many bytes after its last field.
+    TrailingData(usize),
+    PckCertChain(String),
+}
+
+impl Error for QuoteError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+}
+
+impl Display for QuoteError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            QuoteError::Truncated(field) => write!(f, "Quote is truncated in its {}", field),
+            QuoteError::Version(v) => write!(f, "Quote version {} is not supported", v),
+            QuoteError::AttestationKeyType(t) => {
+                write!(f, "Quote Attestation Key type {} is not supported", t)
+            }
+            QuoteError::CertDataType(t) => {
+                write!(f, "Quote Certification Data type {} is not supported", t)
+            }
+            QuoteError::TrailingData(n) => write!(f, "Quote has {} bytes of trailing data", n),
+            QuoteError::PckCertChain(e) => write!(f, "Quote's PCK cert chain is invalid: {}", e),
+        }
+    }
+}
+
+/// A version 3 ECDSA Quote, as produced by the DCAP Quoting Enclave. For its layout, you may
+/// refer to:
+/// https://download.01.org/intel-sgx/dcap-1.0/docs/SGX_ECDSA_QuoteGenReference_DCAP_API_Linux_1.0.pdf
+///
+/// The Quote is only parsed: none of its signatures are verified. Every field is
+/// bounds-checked, so a malformed Quote is an error rather than a panic.
+pub struct Quote<'a> {
+    /// The Quote Header and the ISV enclave Report body, as signed by the Attestation Key.
+    pub header_and_body: &'a [u8],
+
+    /// The ISV enclave Report body (384 bytes).
+    pub report_body: &'a [u8],
+
+    /// The Attestation Key's signature on header_and_body, as raw r || s (64 bytes).
+    pub isv_signature: &'a [u8],
+
+    /// The Attestation Key, as raw x || y coordinates on P-256 (64 bytes).
+    pub attestation_key: &'a [u8],
+
+    /// The Quoting Enclave's Report body (384 bytes), which the PCK signs.
+    pub qe_report: &'a [u8],
+
+    /// The PCK's signature on qe_report, as raw r || s (64 bytes).
+    pub qe_report_signature: &'a [u8],
+
+    /// The QE Authentication Data, which is hashed along with the Attestation Key into the
+    /// QE Report's ReportData.
+    pub auth_data: &'a [u8],
+
+    /// The PCK cert chain, from the PCK leaf certificate to the root.
+    pub pck_cert_chain: Vec<X509>,
+}
+
+impl<'a> Quote<'a> {
+    /// This parses a Quote, which must be exactly as long as its Signature Data says.
+    pub fn parse(quote: &'a [u8]) -> Result<Self, QuoteError> {
+        let mut reader = Reader::new(quote);
+
+        let version = reader.u16("header")?;
+        if version != QUOTE_VERSION {
+            return Err(QuoteError::Version(version));
+        }
+        let key_type = reader.u16("header")?;
+        if key_type != ECDSA_P256 {
+            return Err(QuoteError::AttestationKeyType(key_type));
+        }
+        reader.take(HEADER_SIZE - 4, "header")?;
+        let report_body = reader.take(REPORT_BODY_SIZE, "ISV enclave Report")?;
+        let header_and_body = &quote[..HEADER_SIZE + REPORT_BODY_SIZE];
+
+        let signature_data_len = reader.u32("Signature Data length")? as usize;
+        let mut signature_data = Reader::new(reader.take(signature_data_len, "Signature Data")?);
+        if !reader.is_empty() {
+            return Err(QuoteError::TrailingData(reader.remaining()));
+        }
+
+        let isv_signature = signature_data.take(SIGNATURE_SIZE, "ISV enclave signature")?;
+        let attestation_key = signature_data.take(PUBLIC_KEY_SIZE, "Attestation Key")?;
+        let qe_report = signature_data.take(REPORT_BODY_SIZE, "QE Report")?;
+        let qe_report_signature = signature_data.take(SIGNATURE_SIZE, "QE Report signature")?;
+        let auth_data_len = signature_data.u16("QE Authentication Data length")? as usize;
+        let auth_data = signature_data.take(auth_data_len, "QE Authentication Data")?;
+
+        let cert_data_type = signature_data.u16("Certification Data type")?;
+        if cert_data_type != CERT_DATA_PCK_CERT_CHAIN {
+            return Err(QuoteError::CertDataType(cert_data_type));
+        }
+        let cert_data_len = signature_data.u32("Certification Data size")? as usize;
+        let cert_data = signature_data.take(cert_data_len, "Certification Data")?;
+        if !signature_data.is_empty() {
+            return Err(QuoteError::TrailingData(signature_data.remaining()));
+        }
+
+        let pck_cert_chain =
+            X509::stack_from_pem(cert_data).map_err(|e| QuoteError::PckCertChain(e.to_string()))?;
+        if pck_cert_chain.is_empty() {
+            return Err(QuoteError::PckCertChain("it is empty".to_string()));
+        }
+
+        Ok(Quote {
+            header_and_body,
+            report_body,
+            isv_signature,
+            attestation_key,
+            qe_report,
+            qe_report_signature,
+            auth_data,
+            pck_cert_chain,
+        })
+    }
+
+    /// The PCK leaf certificate, whose key signs the Quoting Enclave's Report.
+    pub fn pck_leaf_cert(&self) -> &X509 {
+        // The chain was checked to be non-empty when the Quote was parsed.
+        &self.pck_cert_chain[0]
+    }
+}
+
+/// A reader of the little-endian fields of a Quote, which fails instead of reading past the
+/// end of its data.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Reader { data }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn remaining(&self) -> usize {
+        self.data.len()
+    }
+
+    fn take(&mut self, len: usize, field: &'static str) -> Result<&'a [u8], QuoteError> {
+        if self.data.len() < len {
+            return Err(QuoteError::Truncated(field));
+        }
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    fn u16(&mut self, field: &'static str) -> Result<u16, QuoteError> {
+        let bytes = self.take(2, field)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self, field: &'static str) -> Result<u32, QuoteError> {
+        let bytes = self.take(4, field)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
@@ -0,0 +1,97 @@
+use crate::der;
+use crate::identity::Policy;
+use crate::verifier::{Collateral, Outcome, QuoteVerifier, Verification};
+use attestation_protocol::{oid, ra_tls};
+use openssl::x509::X509;
+use rustls::client::{ServerCertVerified, ServerCertVerifier};
+use rustls::{Certificate, ServerName};
+use std::error::Error;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// The RaTlsVerifier verifies the RA-TLS certificate of a TLS server running in an enclave,
+/// during the TLS handshake: the certificate's Quote must pass every check of the
+/// QuoteVerifier against the tenant's collateral and policy, and its ReportData must bind the
+/// certificate's public key. The certificate's issuer, validity period and names are not
+/// checked: it is self-signed, and only trusted because of the Quote.
+///
+/// The Quote was generated when the enclave created its certificate, not for the tenant's
+/// request, so its freshness is not checked: the collateral's CRLs and TCB Info decide
+/// whether it is still trusted.
+pub struct RaTlsVerifier {
+    collateral: Collateral,
+    policy: Policy,
+    verification: Mutex<Option<Verification>>,
+}
+
+impl RaTlsVerifier {
+    pub fn new(collateral: Collateral, policy: Policy) -> Self {
+        RaTlsVerifier {
+            collateral,
+            policy,
+            verification: Mutex::new(None),
+        }
+    }
+
+    /// This verifies the Quote of a DER-encoded RA-TLS certificate. A certificate without a
+    /// Quote, or whose Quote cannot be parsed, is an error; otherwise the Verification must
+    /// be inspected with `Verification::verified`.
+    pub fn verify_certificate(&self, certificate: &[u8]) -> Result<Verification, Box<dyn Error>> {
+        let public_key_info = X509::from_der(certificate)?
+            .public_key()?
+            .public_key_to_der()?;
+        let quote_extension = oid::encode(ra_tls::QUOTE_EXTENSION_OID);
+        let quote = der::find_extension(certificate, &quote_extension)?
+            .ok_or("certificate has no RA-TLS Quote extension")?;
+
+        QuoteVerifier::new()
+            .expect_report_data(ra_tls::report_data(&public_key_info))
+            .verify(quote, &self.collateral, &self.policy)
+    }
+
+    /// The Verification of the last certificate verified during a handshake, whether it
+    /// was verified or not.
+    pub fn verification(&self) -> Option<Verification> {
+        self.verification
+            .lock()
+            .expect("Verification lock is poisoned.")
+            .clone()
+    }
+}
+
+impl ServerCertVerifier for RaTlsVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verification = self
+            .verify_certificate(&end_entity.0)
+            .map_err(|e| rustls::Error::InvalidCertificateData(e.to_string()))?;
+        let verified = verification.verified();
+        let failures = verification
+            .checks
+            .iter()
+            .filter_map(|(check, outcome)| match outcome {
+                Outcome::Fail(reason) => Some(format!("{}: {}", check, reason)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        *self
+            .verification
+            .lock()
+            .expect("Verification lock is poisoned.") = Some(verification);
+
+        if !verified {
+            return Err(rustls::Error::InvalidCertificateData(format!(
+                "Quote could not be verified ({})",
+                failures.join("; ")
+            )));
+        }
+        Ok(ServerCertVerified::assertion())
+    }
+}
@@ -0,0 +1,113 @@
+use crate::identity::Policy;
+use crate::verifier::{Collateral, QuoteVerifier, Verification};
+use attestation_protocol::channel::{KeyExchange, Role, SecureChannel};
+use attestation_protocol::{self as protocol, provisioning, Message};
+use openssl::rand::rand_bytes;
+use std::error::Error;
+use std::io::{Read, Write};
+
+/// An attestation of the enclave, requested from the platform's attestation daemon. The
+/// connection to the daemon is kept, as the secure channel with the attested enclave is
+/// relayed over it.
+pub struct Attestation<S> {
+    stream: S,
+    nonce: [u8; 32],
+    quote: Vec<u8>,
+    enclave_data: Vec<u8>,
+    verified: bool,
+}
+
+impl<S: Read + Write> Attestation<S> {
+    /// This requests a Quote of the enclave from the daemon on the other end of the stream.
+    pub fn request(mut stream: S) -> Result<Self, Box<dyn Error>> {
+        // The tenant requests attestation from the platform's attestation daemon by
+        // sending a random nonce. The enclave binds the nonce into its Report, which proves
+        // that the Quote was generated for this request and is not a replay.
+        let mut nonce = [0u8; 32];
+        rand_bytes(&mut nonce)?;
+        protocol::send(&mut stream, &Message::AttestationRequest { nonce })?;
+
+        // The tenant receives a Quote from the platform's attestation daemon, along with
+        // any data the enclave bound into its Report. This Quote verifies the enclave's
+        // self-measurement from its Report.
+        let (quote, enclave_data) = match protocol::recv_reply(&mut stream)? {
+            Message::Quote {
+                quote,
+                enclave_data,
+            } => (quote, enclave_data),
+            other => return Err(protocol::Error::Unexpected(other.name()).into()),
+        };
+
+        Ok(Attestation {
+            stream,
+            nonce,
+            quote,
+            enclave_data,
+            verified: false,
+        })
+    }
+
+    pub fn quote(&self) -> &[u8] {
+        &self.quote
+    }
+
+    /// This verifies the Quote, which must also bind the tenant's nonce and the enclave's
+    /// data, i.e. be fresh.
+    pub fn verify(
+        &mut self,
+        collateral: &Collateral,
+        policy: &Policy,
+    ) -> Result<Verification, Box<dyn Error>> {
+        let report_data = protocol::report_data(&self.nonce, &self.enclave_data);
+        let verification = QuoteVerifier::new()
+            .expect_report_data(report_data)
+            .verify(&self.quote, collateral, policy)?;
+        self.verified = verification.verified();
+        Ok(verification)
+    }
+
+    /// This opens a secure channel with the enclave, whose Quote must have been verified.
+    /// The enclave's data is the public key of its ephemeral key; the tenant sends the
+    /// public key of its own, and the enclave confirms the channel with an empty record.
+    pub fn open_channel(mut self) -> Result<EnclaveSession<S>, Box<dyn Error>> {
+        if !self.verified {
+            return Err("The enclave's Quote has not been verified.".into());
+        }
+        if self.enclave_data.len() != 32 {
+            return Err("The enclave did not bind a public key into its Report.".into());
+        }
+        let mut enclave_public_key = [0u8; 32];
+        enclave_public_key.copy_from_slice(&self.enclave_data);
+
+        let key_exchange = KeyExchange::new();
+        let public_key = key_exchange.public_key();
+        let mut channel = key_exchange.finish(Role::Tenant, &enclave_public_key, &self.nonce)?;
+
+        protocol::send(&mut self.stream, &Message::KeyExchange { public_key })?;
+        let confirmation = match protocol::recv_reply(&mut self.stream)? {
+            Message::Encrypted { record } => channel.open(&record)?,
+            other => return Err(protocol::Error::Unexpected(other.name()).into()),
+        };
+        if !confirmation.is_empty() {
+            return Err("The enclave did not confirm the secure channel.".into());
+        }
+
+        Ok(EnclaveSession {
+            stream: self.stream,
+            channel,
+        })
+    }
+}
+
+/// A secure channel with an attested enclave.
+pub struct EnclaveSession<S> {
+    stream: S,
+    channel: SecureChannel,
+}
+
+impl<S: Read + Write> EnclaveSession<S> {
+    /// This releases a secret to the enclave, and waits for the enclave to acknowledge it.
+    pub fn provision(&mut self, name: &str, secret: &[u8]) -> Result<(), Box<dyn Error>> {
+        provisioning::provision(&mut self.stream, &mut self.channel, name, secret)
+    }
+}
@@ -0,0 +1,191 @@
+use crate::der::{self, DerError, Reader};
+use attestation_protocol::oid;
+use openssl::x509::X509;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+/// The OID of Intel's SGX extension, 1.2.840.113741.1.13.1. Its fields are identified by
+/// OIDs under it, and the TCB's components by OIDs under 1.2.840.113741.1.13.1.2.
+const SGX_EXTENSION_OID: &[u64] = &[1, 2, 840, 113741, 1, 13, 1];
+
+const PPID: u8 = 1;
+const TCB: u8 = 2;
+const PCE_ID: u8 = 3;
+const FMSPC: u8 = 4;
+const SGX_TYPE: u8 = 5;
+
+const TCB_PCESVN: u8 = 17;
+const TCB_CPUSVN: u8 = 18;
+
+/// The platform's TCB as certified by its PCK Certificate.
+#[derive(Debug, Clone, Default)]
+pub struct Tcb {
+    /// The SVNs of the 16 CPU TCB components, in the order they appear in TCB Info.
+    pub comp_svn: [u8; 16],
+    pub pce_svn: u16,
+    pub cpu_svn: [u8; 16],
+}
+
+/// The type of SGX the platform supports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SgxType {
+    Standard,
+    Scalable,
+    ScalableWithIntegrity,
+    Other(u64),
+}
+
+impl Default for SgxType {
+    fn default() -> Self {
+        SgxType::Standard
+    }
+}
+
+impl From<u64> for SgxType {
+    fn from(value: u64) -> Self {
+        match value {
+            0 => SgxType::Standard,
+            1 => SgxType::Scalable,
+            2 => SgxType::ScalableWithIntegrity,
+            other => SgxType::Other(other),
+        }
+    }
+}
+
+/// The SGX extension of a PCK Certificate. For its definition, you may refer to:
+/// https://download.01.org/intel-sgx/dcap-1.0/docs/SGX_PCK_Certificate_CRL_Spec-1.0.pdf
+#[derive(Debug, Clone, Default)]
+pub struct SgxExtension {
+    /// The Platform Provisioning ID, which identifies the platform.
+    pub ppid: [u8; 16],
+    pub tcb: Tcb,
+    pub pce_id: [u8; 2],
+
+    /// The Family-Model-Stepping-Platform-CustomSKU, which identifies the platform model.
+    pub fmspc: [u8; 6],
+    pub sgx_type: SgxType,
+}
+
+impl Display for SgxExtension {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        writeln!(f, "Platform FMSPC: {}", hex::encode(self.fmspc))?;
+        writeln!(f, "Platform PPID:  {}", hex::encode(self.ppid))?;
+        writeln!(
+            f,
+            "Platform PCE-ID: {}, PCESVN: {}, SGX type: {:?}",
+            hex::encode(self.pce_id),
+            self.tcb.pce_svn,
+            self.sgx_type
+        )?;
+        write!(
+            f,
+            "Platform CPUSVN: {}, TCB components: {:?}",
+            hex::encode(self.tcb.cpu_svn),
+            self.tcb.comp_svn
+        )
+    }
+}
+
+impl SgxExtension {
+    /// This extracts the SGX extension from a PCK Certificate.
+    pub fn from_cert(cert: &X509) -> Result<Self, Box<dyn Error>> {
+        let der = cert.to_der()?;
+        let extension = der::find_extension(&der, &oid::encode(SGX_EXTENSION_OID))?
+            .ok_or("PCK Certificate has no SGX extension")?;
+        Ok(SgxExtension::parse(extension)?)
+    }
+
+    /// The extension is a SEQUENCE of (OID, value) pairs.
+    fn parse(extension: &[u8]) -> Result<Self, DerError> {
+        let mut sgx = SgxExtension::default();
+        let mut found = 0u32;
+
+        let extension = Reader::new(extension).read(der::SEQUENCE)?;
+        for (arc, tag, value) in fields(extension, &oid::encode(SGX_EXTENSION_OID))? {
+            match arc {
+                PPID => sgx.ppid = fixed(tag, value)?,
+                TCB if tag == der::SEQUENCE => sgx.tcb = parse_tcb(value)?,
+                PCE_ID => sgx.pce_id = fixed(tag, value)?,
+                FMSPC => sgx.fmspc = fixed(tag, value)?,
+                SGX_TYPE if tag == der::ENUMERATED => {
+                    sgx.sgx_type = SgxType::from(der::unsigned(value)?)
+                }
+                // The Platform CA also certifies the platform instance and its
+                // configuration, which are not used.
+                _ => continue,
+            }
+            found |= 1 << arc;
+        }
+
+        // The PPID, TCB, PCE-ID, FMSPC and SGX type must all be present.
+        if found != 0x3e {
+            return Err(DerError("SGX extension is missing a field"));
+        }
+        Ok(sgx)
+    }
+}
+
+fn parse_tcb(tcb: &[u8]) -> Result<Tcb, DerError> {
+    let tcb_oid = oid::encode(&[SGX_EXTENSION_OID, &[u64::from(TCB)]].concat());
+
+    let mut result = Tcb::default();
+    let mut found = 0u32;
+    for (arc, tag, value) in fields(tcb, &tcb_oid)? {
+        match arc {
+            1..=16 if tag == der::INTEGER => {
+                result.comp_svn[arc as usize - 1] = narrow(der::unsigned(value)?)?;
+                found |= 1 << arc;
+            }
+            TCB_PCESVN if tag == der::INTEGER => {
+                result.pce_svn = narrow(der::unsigned(value)?)?;
+                found |= 1 << arc;
+            }
+            TCB_CPUSVN => {
+                result.cpu_svn = fixed(tag, value)?;
+                found |= 1 << arc;
+            }
+            _ => (),
+        }
+    }
+
+    // Components 1 to 16, the PCESVN and the CPUSVN must all be present.
+    if found != 0x7fffe {
+        return Err(DerError("TCB is missing a component"));
+    }
+    Ok(result)
+}
+
+/// A field of the extension: the last arc of its OID, the value's tag and the value's
+/// contents.
+type Field<'a> = (u8, u8, &'a [u8]);
+
+/// This splits the contents of a SEQUENCE of (OID, value) pairs, keeping those whose OID is
+/// directly under parent.
+fn fields<'a>(sequence: &'a [u8], parent: &[u8]) -> Result<Vec<Field<'a>>, DerError> {
+    let mut fields = Vec::new();
+    let mut entries = Reader::new(sequence);
+    while !entries.is_empty() {
+        let mut entry = Reader::new(entries.read(der::SEQUENCE)?);
+        let oid = entry.read(der::OBJECT_IDENTIFIER)?;
+        let (tag, value) = entry.read_any()?;
+
+        // The arcs of the fields are all below 128, so they are encoded as a single byte.
+        if oid.len() == parent.len() + 1 && oid.starts_with(parent) && oid[parent.len()] < 0x80 {
+            fields.push((oid[parent.len()], tag, value));
+        }
+    }
+    Ok(fields)
+}
+
+fn fixed<T: Default + AsMut<[u8]>>(tag: u8, value: &[u8]) -> Result<T, DerError> {
+    let mut result = T::default();
+    if tag != der::OCTET_STRING || value.len() != result.as_mut().len() {
+        return Err(DerError("field has the wrong type or size"));
+    }
+    result.as_mut().copy_from_slice(value);
+    Ok(result)
+}
+
+fn narrow<T: std::convert::TryFrom<u64>>(value: u64) -> Result<T, DerError> {
+    T::try_from(value).map_err(|_| DerError("SVN out of range"))
+}
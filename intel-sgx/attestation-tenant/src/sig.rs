@@ -1,7 +1,8 @@
-use dcap_ql::quote::*;
-use failure::Error;
 use openssl::{bn::BigNum, ecdsa::EcdsaSig};
 use std::convert::TryFrom;
+use std::error::Error;
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
 
 /// This struct creates a Signature from raw r and s values, which can
 /// be converted to DER form with the method below.
@@ -40,8 +41,11 @@ impl Default for Signature {
 
 // turns &[u8] into Signature
 impl TryFrom<&[u8]> for Signature {
-    type Error = Error;
+    type Error = Box<dyn Error>;
     fn try_from(value: &[u8]) -> Result<Self> {
+        if value.len() != 64 {
+            return Err(format!("Signature must be 64 bytes long, not {}.", value.len()).into());
+        }
         let mut r: [u8; 32] = Default::default();
         let mut s: [u8; 32] = Default::default();
         r.copy_from_slice(&value[0..32]);
@@ -53,7 +57,7 @@ impl TryFrom<&[u8]> for Signature {
 
 // turns Signature into ecdsa
 impl TryFrom<&Signature> for EcdsaSig {
-    type Error = Error;
+    type Error = Box<dyn Error>;
     fn try_from(value: &Signature) -> Result<Self> {
         let r = BigNum::from_slice(&value.r)?;
         let s = BigNum::from_slice(&value.s)?;
@@ -63,7 +67,7 @@ impl TryFrom<&Signature> for EcdsaSig {
 
 // turns a Signature in to an ECDSA DER Vector
 impl TryFrom<&Signature> for Vec<u8> {
-    type Error = Error;
+    type Error = Box<dyn Error>;
     fn try_from(value: &Signature) -> Result<Self> {
         Ok(EcdsaSig::try_from(value)?.to_der()?)
     }
@@ -0,0 +1,105 @@
+use crate::{cert_chain::CertChain, crl, key::Key, sig::Signature};
+use openssl::{
+    asn1::Asn1Time,
+    x509::{X509Crl, X509},
+};
+use serde_json::value::RawValue;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::error::Error;
+
+/// Intel's JSON collateral (TCB Info and QE Identity) is made of a body and the TCB Signing
+/// Key's signature over the exact bytes of the body, e.g.
+/// `{ "tcbInfo": { ... }, "signature": "<hex-encoded r || s>" }`.
+pub struct SignedJson {
+    body: String,
+    signature: Vec<u8>,
+    signing_chain: Vec<X509>,
+}
+
+impl SignedJson {
+    /// This parses the collateral, whose body has one of body_names, and the PEM-encoded TCB
+    /// signing chain (the TCB Signing Certificate, followed by the Root CA).
+    pub fn from_json(
+        json: &str,
+        body_names: &[&str],
+        signing_chain: &[u8],
+    ) -> Result<Self, Box<dyn Error>> {
+        let fields: BTreeMap<String, &RawValue> = serde_json::from_str(json)?;
+        let body = body_names
+            .iter()
+            .find_map(|name| fields.get(*name))
+            .ok_or_else(|| format!("collateral has no {}", body_names.join(" or ")))?;
+        let signature: String = serde_json::from_str(
+            fields
+                .get("signature")
+                .ok_or("collateral is unsigned")?
+                .get(),
+        )?;
+
+        let signing_chain = X509::stack_from_pem(signing_chain)?;
+        if signing_chain.len() < 2 {
+            return Err("TCB signing chain must hold the signing cert and the root".into());
+        }
+
+        Ok(SignedJson {
+            body: body.get().to_string(),
+            signature: hex::decode(&signature)?,
+            signing_chain,
+        })
+    }
+
+    /// The body, as it was signed.
+    pub fn body(&self) -> &str {
+        &self.body
+    }
+
+    /// This verifies that the body was signed by a TCB Signing Certificate issued by the
+    /// tenant's root, which is not revoked, and that the collateral is current according to
+    /// its issue and next update dates.
+    pub fn verify(
+        &self,
+        root: &X509,
+        crls: &[X509Crl],
+        issue_date: &str,
+        next_update: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let (signing_cert, chain) = self
+            .signing_chain
+            .split_first()
+            .ok_or("TCB signing chain is empty")?;
+        if chain.last().map(|c| c.to_der()).transpose()? != Some(root.to_der()?) {
+            return Err("TCB signing chain does not end in the tenant's root".into());
+        }
+
+        let cert_chain = CertChain::new_from_chain(chain.to_vec(), signing_cert);
+        cert_chain.len_ok()?;
+        cert_chain.verify_issuers()?;
+        cert_chain.verify_sigs()?;
+        crl::check_revocation(chain, signing_cert, crls)?;
+
+        let signature = Signature::try_from(&self.signature[..])?.to_der_vec()?;
+        Key::new_from_pubkey(signing_cert.public_key()?)
+            .verify_sig(self.body.as_bytes(), &signature)
+            .map_err(|_| "collateral signature is invalid")?;
+
+        let now = Asn1Time::days_from_now(0)?;
+        if parse_date(issue_date)? > now || parse_date(next_update)? < now {
+            return Err("collateral is not current".into());
+        }
+        Ok(())
+    }
+}
+
+/// The dates in Intel's collateral are in ISO 8601 format, e.g. 2019-08-01T10:18:20Z.
+fn parse_date(date: &str) -> Result<Asn1Time, Box<dyn Error>> {
+    let digits: String = date
+        .chars()
+        .filter(|c| c.is_ascii_digit())
+        .take(14)
+        .collect();
+    if digits.len() != 14 || !date.ends_with('Z') {
+        return Err(format!("invalid date {}", date).into());
+    }
+    Ok(Asn1Time::from_str(&format!("{}Z", digits))?)
+}
@@ -0,0 +1,153 @@
+use crate::{sgx_ext::SgxExtension, signed::SignedJson};
+use openssl::x509::{X509Crl, X509};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+/// The status of a TCB level, as assigned by Intel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum TcbStatus {
+    UpToDate,
+    SWHardeningNeeded,
+    ConfigurationNeeded,
+    ConfigurationAndSWHardeningNeeded,
+    OutOfDate,
+    OutOfDateConfigurationNeeded,
+    Revoked,
+}
+
+impl Display for TcbStatus {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// The platform's TCB level, as determined from TCB Info.
+#[derive(Debug, Clone)]
+pub struct TcbEvaluation {
+    pub status: TcbStatus,
+
+    /// The Intel Security Advisories that apply to the platform's TCB level.
+    pub advisory_ids: Vec<String>,
+}
+
+impl Display for TcbEvaluation {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "TCB status: {}", self.status)?;
+        if !self.advisory_ids.is_empty() {
+            write!(f, ", advisories: {}", self.advisory_ids.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TcbInfoBody {
+    version: u32,
+    issue_date: String,
+    next_update: String,
+    fmspc: String,
+    pce_id: String,
+    tcb_levels: Vec<TcbLevel>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TcbLevel {
+    tcb: TcbComponents,
+    tcb_status: TcbStatus,
+    #[serde(default, rename = "advisoryIDs")]
+    advisory_ids: Vec<String>,
+}
+
+/// Version 2 of TCB Info lists the CPU TCB components as sgxtcbcomp01svn to
+/// sgxtcbcomp16svn; version 3 lists them in sgxtcbcomponents.
+#[derive(Deserialize)]
+struct TcbComponents {
+    #[serde(default)]
+    sgxtcbcomponents: Vec<TcbComponent>,
+    pcesvn: u16,
+    #[serde(flatten)]
+    other: BTreeMap<String, serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct TcbComponent {
+    svn: u8,
+}
+
+impl TcbComponents {
+    fn comp_svn(&self) -> Result<[u8; 16], Box<dyn Error>> {
+        let mut comp_svn = [0u8; 16];
+        for (i, svn) in comp_svn.iter_mut().enumerate() {
+            let value = match self.sgxtcbcomponents.get(i) {
+                Some(component) => Some(u64::from(component.svn)),
+                None => self
+                    .other
+                    .get(&format!("sgxtcbcomp{:02}svn", i + 1))
+                    .and_then(|v| v.as_u64()),
+            };
+            *svn = value
+                .and_then(|v| u8::try_from(v).ok())
+                .ok_or_else(|| format!("TCB level is missing component {}", i + 1))?;
+        }
+        Ok(comp_svn)
+    }
+}
+
+/// Intel's TCB Info for one FMSPC, i.e. one platform model. It lists the TCB levels of the
+/// platform model and their status, and is signed by the TCB Signing Key. For more
+/// information, you may refer to:
+/// https://api.portal.trustedservices.intel.com/documentation#pcs-tcb-info
+pub struct TcbInfo {
+    body: TcbInfoBody,
+    signed: SignedJson,
+}
+
+impl TcbInfo {
+    /// The TcbInfo is parsed from the TCB Info JSON and the PEM-encoded TCB signing chain
+    /// (the TCB Signing Certificate, followed by the Root CA).
+    pub fn from_json(json: &str, signing_chain: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let signed = SignedJson::from_json(json, &["tcbInfo"], signing_chain)?;
+        let body: TcbInfoBody = serde_json::from_str(signed.body())?;
+        if body.version != 2 && body.version != 3 {
+            return Err(format!("TCB Info version {} is not supported", body.version).into());
+        }
+        Ok(TcbInfo { body, signed })
+    }
+
+    /// This verifies that the TCB Info was signed by a TCB Signing Certificate issued by
+    /// the tenant's root, which is not revoked, and that the TCB Info is current.
+    pub fn verify(&self, root: &X509, crls: &[X509Crl]) -> Result<(), Box<dyn Error>> {
+        self.signed
+            .verify(root, crls, &self.body.issue_date, &self.body.next_update)
+            .map_err(|e| format!("TCB Info: {}", e).into())
+    }
+
+    /// This determines the TCB level of the platform from its PCK Certificate. The TCB
+    /// levels are sorted from the highest to the lowest, and the platform is at the first
+    /// level whose components are all lower than or equal to the platform's.
+    pub fn evaluate(&self, sgx: &SgxExtension) -> Result<TcbEvaluation, Box<dyn Error>> {
+        if hex::decode(&self.body.fmspc)? != sgx.fmspc {
+            return Err("TCB Info is for another FMSPC than the PCK Certificate".into());
+        }
+        if hex::decode(&self.body.pce_id)? != sgx.pce_id {
+            return Err("TCB Info is for another PCE-ID than the PCK Certificate".into());
+        }
+
+        for level in &self.body.tcb_levels {
+            let comp_svn = level.tcb.comp_svn()?;
+            let cpu_ok = sgx.tcb.comp_svn.iter().zip(&comp_svn).all(|(p, l)| p >= l);
+            if cpu_ok && sgx.tcb.pce_svn >= level.tcb.pcesvn {
+                return Ok(TcbEvaluation {
+                    status: level.tcb_status,
+                    advisory_ids: level.advisory_ids.clone(),
+                });
+            }
+        }
+        Err("platform's TCB is lower than every TCB level in TCB Info".into())
+    }
+}
@@ -0,0 +1,438 @@
+use crate::{
+    cert_chain::CertChain,
+    crl, identity,
+    identity::Policy,
+    key::Key,
+    qe_identity::QeIdentity,
+    quote::Quote,
+    sgx_ext::SgxExtension,
+    sig::Signature,
+    tcb::{TcbEvaluation, TcbInfo, TcbStatus},
+};
+use openssl::x509::{X509Crl, X509};
+use sgx_isa::{AttributesFlags, Report};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+/// The material needed to verify a Quote that does not come with the Quote itself. It is
+/// retrieved separately by the tenant and is trusted.
+pub struct Collateral {
+    /// The PCK certificate chain from Intel, ordered from the intermediate CA to the root
+    /// CA. The root is the tenant's trust anchor.
+    pub pck_chain: Vec<X509>,
+
+    /// The CRLs issued by the CAs of the PCK cert chain: the Root CA CRL and the PCK
+    /// Processor or Platform CA CRL.
+    pub crls: Vec<X509Crl>,
+
+    /// Intel's TCB Info for the platform's FMSPC.
+    pub tcb_info: Option<TcbInfo>,
+
+    /// Intel's identity of the genuine Quoting Enclave.
+    pub qe_identity: Option<QeIdentity>,
+}
+
+impl Collateral {
+    /// The Collateral is loaded from the PEM-encoded PCK certificate chain.
+    pub fn from_pem(pck_chain: &[u8]) -> Result<Self, Box<dyn Error>> {
+        Ok(Collateral {
+            pck_chain: X509::stack_from_pem(pck_chain)?,
+            crls: Vec::new(),
+            tcb_info: None,
+            qe_identity: None,
+        })
+    }
+
+    /// This adds a PEM or DER-encoded CRL to the Collateral.
+    pub fn add_crl(&mut self, crl: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.crls.push(crl::load(crl)?);
+        Ok(())
+    }
+
+    /// This sets the TCB Info JSON and its PEM-encoded signing chain.
+    pub fn set_tcb_info(
+        &mut self,
+        tcb_info: &str,
+        signing_chain: &[u8],
+    ) -> Result<(), Box<dyn Error>> {
+        self.tcb_info = Some(TcbInfo::from_json(tcb_info, signing_chain)?);
+        Ok(())
+    }
+
+    /// This sets the QE Identity JSON and its PEM-encoded signing chain, which is the same
+    /// as TCB Info's.
+    pub fn set_qe_identity(
+        &mut self,
+        qe_identity: &str,
+        signing_chain: &[u8],
+    ) -> Result<(), Box<dyn Error>> {
+        self.qe_identity = Some(QeIdentity::from_json(qe_identity, signing_chain)?);
+        Ok(())
+    }
+}
+
+/// The checks performed on a Quote.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Check {
+    /// The Quote's PCK Certificate chains up to the tenant's root.
+    PckChain,
+
+    /// Neither the PCK cert chain nor the Quote's PCK Certificate is revoked.
+    Revocation,
+
+    /// The TCB Info is signed by Intel, current, and covers the platform's TCB.
+    TcbInfo,
+
+    /// The platform's TCB status is allowed by the tenant's policy.
+    TcbStatus,
+
+    /// The Attestation Key signed the Quote header and the ISV enclave Report body.
+    AkSignature,
+
+    /// The PCK signed the Quoting Enclave's Report.
+    QeReportSignature,
+
+    /// The Quoting Enclave's Report binds the Attestation Key and authentication data.
+    QeReportDataHash,
+
+    /// The Quoting Enclave is the genuine one according to QE Identity, and its TCB status
+    /// is allowed by the tenant's policy.
+    QeIdentity,
+
+    /// The ISV enclave's ReportData is the value expected by the verifier.
+    ReportData,
+
+    /// The ISV enclave's identity satisfies the tenant's policy.
+    IdentityPolicy,
+}
+
+impl Display for Check {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        let name = match self {
+            Check::PckChain => "PCK cert chain",
+            Check::Revocation => "PCK cert chain revocation",
+            Check::TcbInfo => "TCB Info",
+            Check::TcbStatus => "Platform TCB status",
+            Check::AkSignature => "AK signature on Quote header || report body",
+            Check::QeReportSignature => "PCK signature on QE Report",
+            Check::QeReportDataHash => "QE Report's hash of AK || auth data",
+            Check::QeIdentity => "QE Identity",
+            Check::ReportData => "ISV enclave ReportData",
+            Check::IdentityPolicy => "ISV enclave identity policy",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The outcome of a single check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Pass,
+    Fail(String),
+
+    /// The check was not requested.
+    Skip,
+}
+
+impl<E: Display> From<Result<(), E>> for Outcome {
+    fn from(result: Result<(), E>) -> Self {
+        match result {
+            Ok(()) => Outcome::Pass,
+            Err(e) => Outcome::Fail(e.to_string()),
+        }
+    }
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Outcome::Pass => write!(f, "PASS"),
+            Outcome::Fail(reason) => write!(f, "FAIL ({})", reason),
+            Outcome::Skip => write!(f, "SKIP"),
+        }
+    }
+}
+
+/// The identity of the attested enclave, as found in the ISV enclave Report body.
+#[derive(Debug, Clone)]
+pub struct Identity {
+    pub mrenclave: [u8; 32],
+    pub mrsigner: [u8; 32],
+    pub isv_prod_id: u16,
+    pub isv_svn: u16,
+    pub attributes: AttributesFlags,
+    pub report_data: [u8; 64],
+}
+
+impl From<&Report> for Identity {
+    fn from(report: &Report) -> Self {
+        Identity {
+            mrenclave: report.mrenclave,
+            mrsigner: report.mrsigner,
+            isv_prod_id: report.isvprodid,
+            isv_svn: report.isvsvn,
+            attributes: report.attributes.flags,
+            report_data: report.reportdata,
+        }
+    }
+}
+
+impl Display for Identity {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        writeln!(f, "Enclave MRENCLAVE: {}", hex::encode(self.mrenclave))?;
+        writeln!(f, "Enclave MRSIGNER:  {}", hex::encode(self.mrsigner))?;
+        write!(
+            f,
+            "Enclave ISVPRODID: {}, ISVSVN: {}, attributes: {:?}",
+            self.isv_prod_id, self.isv_svn, self.attributes
+        )
+    }
+}
+
+/// The result of verifying a Quote: the outcome of every check, in the order they were
+/// performed, and the identity of the attested enclave.
+#[derive(Debug, Clone)]
+pub struct Verification {
+    pub checks: Vec<(Check, Outcome)>,
+    pub identity: Identity,
+
+    /// The platform, as described by the PCK Certificate's SGX extension.
+    pub platform: Option<SgxExtension>,
+
+    /// The platform's TCB level, if it could be determined.
+    pub tcb: Option<TcbEvaluation>,
+
+    /// The status of the Quoting Enclave's TCB level, if it could be determined.
+    pub qe_tcb_status: Option<TcbStatus>,
+}
+
+impl Verification {
+    /// This returns the outcome of the given check.
+    pub fn outcome(&self, check: Check) -> Option<&Outcome> {
+        self.checks
+            .iter()
+            .find(|(c, _)| *c == check)
+            .map(|(_, outcome)| outcome)
+    }
+
+    /// The Quote is verified if none of the checks failed.
+    pub fn verified(&self) -> bool {
+        self.checks
+            .iter()
+            .all(|(_, outcome)| !matches!(outcome, Outcome::Fail(_)))
+    }
+}
+
+impl Display for Verification {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        for (check, outcome) in &self.checks {
+            writeln!(f, "{}: {}", check, outcome)?;
+        }
+        if let Some(platform) = &self.platform {
+            writeln!(f, "{}", platform)?;
+        }
+        if let Some(tcb) = &self.tcb {
+            writeln!(f, "{}", tcb)?;
+        }
+        if let Some(qe_tcb_status) = &self.qe_tcb_status {
+            writeln!(f, "QE TCB status: {}", qe_tcb_status)?;
+        }
+        write!(f, "{}", self.identity)
+    }
+}
+
+/// The QuoteVerifier verifies a DCAP Quote (version 3, ECDSA P-256) against the tenant's
+/// collateral and identity policy:
+/// 1. That the Quote's PCK Certificate (embedded in the Cert Data) is valid.
+/// 2. That, according to the collateral's CRLs, no certificate of the PCK cert chain is
+///    revoked.
+/// 3. That the collateral's TCB Info is valid, and that the platform's TCB level, as
+///    certified by the PCK Certificate, has a status allowed by the tenant's policy.
+/// 4. That the Attestation Key signed the Quote.
+/// 5. That the PCK Certificate's Key signed the Quoting Enclave's Report.
+/// 6. That the hashed material (containing the Attestation Key) signed by the PCK is valid,
+///    and that the rest of the Quoting Enclave's ReportData is zero.
+/// 7. That the Quoting Enclave matches the collateral's QE Identity, and that its TCB status
+///    is allowed by the tenant's policy.
+/// 8. Optionally, that the enclave's ReportData has the expected value, e.g. that it binds
+///    the tenant's nonce.
+/// 9. That the enclave's identity (MRENCLAVE, MRSIGNER, ISVPRODID, ISVSVN and attributes)
+///    satisfies the tenant's identity policy.
+///
+/// For more information on Intel's PCK and certificate chains, you may refer to:
+/// https://download.01.org/intel-sgx/dcap-1.0/docs/SGX_PCK_Certificate_CRL_Spec-1.0.pdf
+///
+/// For more informtation on Intel's Attestation Key and the Quote, you may refer to:
+/// https://download.01.org/intel-sgx/dcap-1.0/docs/SGX_ECDSA_QuoteGenReference_DCAP_API_Linux_1.0.pdf
+#[derive(Debug, Clone, Default)]
+pub struct QuoteVerifier {
+    report_data: Option<[u8; 64]>,
+}
+
+impl QuoteVerifier {
+    pub fn new() -> Self {
+        QuoteVerifier::default()
+    }
+
+    /// The enclave's ReportData must be equal to report_data. Without it, the ReportData
+    /// check is skipped.
+    pub fn expect_report_data(mut self, report_data: [u8; 64]) -> Self {
+        self.report_data = Some(report_data);
+        self
+    }
+
+    /// This verifies the Quote. A Quote that cannot be parsed is an error; otherwise every
+    /// check is performed and its outcome recorded in the Verification, which must be
+    /// inspected with `Verification::verified`.
+    pub fn verify(
+        &self,
+        quote: &[u8],
+        collateral: &Collateral,
+        policy: &Policy,
+    ) -> Result<Verification, Box<dyn Error>> {
+        // The Quote comes from the platform and is not trusted: it is parsed without
+        // verifying anything, and every malformed Quote is rejected here.
+        let quote = Quote::parse(quote)?;
+
+        // The ISV enclave Report body describes the identity of the attested enclave.
+        let report = identity::report_from_body(quote.report_body)
+            .ok_or("ISV enclave Report is malformed")?;
+
+        // The Quote's Certification Data contains the PCK Certificate, whose key signs the
+        // Quoting Enclave's Report.
+        let pck_leaf_cert = quote.pck_leaf_cert().clone();
+
+        let mut checks = Vec::new();
+
+        // The PCK chain is reconstructed with the Quote's leaf cert added to end of the
+        // tenant's chain, and its issuers and signatures are verified.
+        checks.push((
+            Check::PckChain,
+            verify_pck_chain(&collateral.pck_chain, &pck_leaf_cert).into(),
+        ));
+
+        // The CRLs are verified against the PCK cert chain, and the chain's certificates
+        // are checked against them.
+        checks.push((
+            Check::Revocation,
+            crl::check_revocation(&collateral.pck_chain, &pck_leaf_cert, &collateral.crls).into(),
+        ));
+
+        // The PCK Certificate's SGX extension describes the platform. The platform's TCB
+        // level is looked up in the TCB Info, using the TCB components from the extension.
+        let platform = SgxExtension::from_cert(&pck_leaf_cert);
+        let tcb_evaluation = || -> Result<TcbEvaluation, Box<dyn Error>> {
+            let platform = platform.as_ref().map_err(|e| e.to_string())?;
+            let tcb_info = collateral
+                .tcb_info
+                .as_ref()
+                .ok_or("no TCB Info was supplied")?;
+            let root = collateral
+                .pck_chain
+                .last()
+                .ok_or("PCK cert chain is empty")?;
+            tcb_info.verify(root, &collateral.crls)?;
+            tcb_info.evaluate(platform)
+        };
+        let tcb = match tcb_evaluation() {
+            Ok(tcb) => {
+                checks.push((Check::TcbInfo, Outcome::Pass));
+                Some(tcb)
+            }
+            Err(e) => {
+                checks.push((Check::TcbInfo, Outcome::Fail(e.to_string())));
+                None
+            }
+        };
+        let tcb_status = match &tcb {
+            Some(tcb) if policy.allowed_tcb_statuses.contains(&tcb.status) => Outcome::Pass,
+            Some(tcb) => Outcome::Fail(format!("{} is not allowed", tcb.status)),
+            None => Outcome::Fail("TCB status could not be determined".into()),
+        };
+        checks.push((Check::TcbStatus, tcb_status));
+
+        // The Attestation Key's signature on the Quote is verified.
+        let ak_signature = || -> Result<(), Box<dyn Error>> {
+            let attestation_key = Key::new_from_xy(quote.attestation_key)?;
+            let signature = Signature::try_from(quote.isv_signature)?.to_der_vec()?;
+            attestation_key.verify_sig(quote.header_and_body, &signature)
+        };
+        checks.push((Check::AkSignature, ak_signature().into()));
+
+        // The PCK's signature on the Quoting Enclave's Report is verified.
+        let pck_key = || -> Result<Key, Box<dyn Error>> {
+            Ok(Key::new_from_pubkey(pck_leaf_cert.public_key()?))
+        };
+        let qe_report_signature = || -> Result<(), Box<dyn Error>> {
+            let signature = Signature::try_from(quote.qe_report_signature)?.to_der_vec()?;
+            pck_key()?.verify_sig(quote.qe_report, &signature)
+        };
+        checks.push((Check::QeReportSignature, qe_report_signature().into()));
+
+        // This verifies that the QE Report's ReportData, signed by the PCK, holds the hash
+        // of the Attestation Key and the QE Authentication Data.
+        let qe_report_hash = || -> Result<(), Box<dyn Error>> {
+            let qe_report =
+                identity::report_from_body(quote.qe_report).ok_or("QE Report is malformed")?;
+            let mut unhashed_data = Vec::new();
+            unhashed_data.extend(quote.attestation_key);
+            unhashed_data.extend(quote.auth_data);
+            pck_key()?.verify_hash(&qe_report.reportdata[..32], unhashed_data)?;
+
+            // Only the first half of the ReportData holds the hash; the rest must be zero.
+            if qe_report.reportdata[32..].iter().any(|&b| b != 0) {
+                return Err("upper half of the QE ReportData is not zero".into());
+            }
+            Ok(())
+        };
+        checks.push((Check::QeReportDataHash, qe_report_hash().into()));
+
+        // The Quoting Enclave's identity is checked against QE Identity: otherwise, any
+        // enclave whose Report is signed by the PCK could stand in for the Quoting Enclave.
+        let qe_tcb_status = || -> Result<TcbStatus, Box<dyn Error>> {
+            let qe_identity = collateral
+                .qe_identity
+                .as_ref()
+                .ok_or("no QE Identity was supplied")?;
+            let root = collateral
+                .pck_chain
+                .last()
+                .ok_or("PCK cert chain is empty")?;
+            qe_identity.verify(root, &collateral.crls)?;
+            qe_identity.appraise(quote.qe_report)
+        };
+        let qe_tcb_status = qe_tcb_status();
+        let qe_outcome = match &qe_tcb_status {
+            Ok(status) if policy.allowed_tcb_statuses.contains(status) => Outcome::Pass,
+            Ok(status) => Outcome::Fail(format!("QE TCB status {} is not allowed", status)),
+            Err(e) => Outcome::Fail(e.to_string()),
+        };
+        checks.push((Check::QeIdentity, qe_outcome));
+
+        let report_data = match self.report_data {
+            Some(expected) if expected[..] == report.reportdata[..] => Outcome::Pass,
+            Some(_) => Outcome::Fail("ReportData does not have the expected value".into()),
+            None => Outcome::Skip,
+        };
+        checks.push((Check::ReportData, report_data));
+
+        // The enclave's identity is checked against the tenant's policy.
+        checks.push((Check::IdentityPolicy, policy.appraise(&report).into()));
+
+        Ok(Verification {
+            checks,
+            identity: Identity::from(&report),
+            platform: platform.ok(),
+            tcb,
+            qe_tcb_status: qe_tcb_status.ok(),
+        })
+    }
+}
+
+fn verify_pck_chain(pck_chain: &[X509], pck_leaf_cert: &X509) -> Result<(), Box<dyn Error>> {
+    let cert_chain = CertChain::new_from_chain(pck_chain.to_vec(), pck_leaf_cert);
+    cert_chain.len_ok()?;
+    cert_chain.verify_issuers()?;
+    cert_chain.verify_sigs()
+}
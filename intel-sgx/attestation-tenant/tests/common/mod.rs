@@ -0,0 +1,24 @@
+use attestation_sim::QuotingEnclave;
+use attestation_tenant::Collateral;
+
+/// The tenant's collateral for the simulated Quoting Enclave: its PCK cert chain, the CRLs
+/// of its CAs, its TCB Info and QE Identity.
+pub fn collateral(qe: &QuotingEnclave) -> Collateral {
+    let pki = qe.pki();
+
+    let mut collateral = Collateral::from_pem(&pki.pck_chain_pem().unwrap()).unwrap();
+    collateral
+        .add_crl(&pki.root.issue_crl(&[], 30).unwrap())
+        .unwrap();
+    collateral
+        .add_crl(&pki.pck_ca.issue_crl(&[], 30).unwrap())
+        .unwrap();
+    let tcb_chain = pki.tcb_chain_pem().unwrap();
+    collateral
+        .set_tcb_info(&qe.tcb_info().unwrap(), &tcb_chain)
+        .unwrap();
+    collateral
+        .set_qe_identity(&qe.qe_identity().unwrap(), &tcb_chain)
+        .unwrap();
+    collateral
+}
@@ -0,0 +1,33 @@
+//! Parsing of the tenant's identity policy file.
+
+use attestation_tenant::{Policy, TcbStatus};
+
+#[test]
+fn policy_file_is_parsed() {
+    let policy = Policy::from_json(&format!(
+        r#"{{ "mrsigner": ["{}"], "isv_prod_id": 2, "min_isv_svn": 3 }}"#,
+        "5a".repeat(32)
+    ))
+    .unwrap();
+    assert!(policy.mrenclave.is_empty());
+    assert_eq!(policy.mrsigner, vec![[0x5a; 32]]);
+    assert_eq!(policy.isv_prod_id, Some(2));
+    assert_eq!(policy.min_isv_svn, 3);
+    assert!(!policy.allow_debug);
+    assert_eq!(policy.allowed_tcb_statuses, vec![TcbStatus::UpToDate]);
+}
+
+#[test]
+fn policy_must_pin_the_enclave() {
+    assert!(Policy::new(Vec::new(), Vec::new()).is_err());
+    assert!(Policy::from_json(r#"{ "min_isv_svn": 3 }"#).is_err());
+}
+
+#[test]
+fn misspelled_key_is_rejected() {
+    let json = format!(
+        r#"{{ "mrenclave": ["{}"], "min_isvsvn": 3 }}"#,
+        "ab".repeat(32)
+    );
+    assert!(Policy::from_json(&json).is_err());
+}
@@ -0,0 +1,151 @@
+//! Secret provisioning, round trip: the tenant attests a simulated enclave through a
+//! simulated daemon, opens the secure channel with it and releases its secrets, which the
+//! enclave must acknowledge.
+
+mod common;
+
+use attestation_protocol::channel::{KeyExchange, Role};
+use attestation_protocol::provisioning::{Receipt, SecretStore};
+use attestation_protocol::{self as protocol, Message};
+use attestation_sim::{EnclaveIdentity, Platform, QuotingEnclave};
+use attestation_tenant::{Attestation, Policy};
+use std::os::unix::net::UnixStream;
+use std::thread::{self, JoinHandle};
+
+/// The daemon and the enclave of a simulated platform, as the tenant sees them through its
+/// connection to the daemon. The platform serves the connection until the tenant closes it,
+/// and returns the secrets provisioned to the enclave. A tampering platform alters the
+/// records of the secure channel it relays.
+fn simulated_platform(
+    qe: QuotingEnclave,
+    enclave: EnclaveIdentity,
+    mut stream: UnixStream,
+    tamper: bool,
+) -> JoinHandle<SecretStore> {
+    thread::spawn(move || {
+        let mut secrets = SecretStore::new();
+        let mut key_exchange = None;
+        let mut channel = None;
+
+        while let Some(message) = protocol::recv(&mut stream).unwrap() {
+            let reply = match message {
+                Message::AttestationRequest { nonce } => {
+                    let new_key_exchange = KeyExchange::new();
+                    let enclave_data = new_key_exchange.public_key().to_vec();
+                    let report_data = protocol::report_data(&nonce, &enclave_data);
+                    let report = enclave.report(&qe.target_info(), &report_data).unwrap();
+                    key_exchange = Some((new_key_exchange, nonce));
+                    Message::Quote {
+                        quote: qe.quote(&report).unwrap(),
+                        enclave_data,
+                    }
+                }
+                Message::KeyExchange { public_key } => {
+                    let (key_exchange, nonce) = key_exchange.take().unwrap();
+                    let mut new_channel = key_exchange
+                        .finish(Role::Enclave, &public_key, &nonce)
+                        .unwrap();
+                    let record = new_channel.seal(&[]).unwrap();
+                    channel = Some(new_channel);
+                    Message::Encrypted { record }
+                }
+                Message::Encrypted { mut record } => {
+                    if tamper {
+                        record[0] ^= 0x01;
+                    }
+                    match secrets.receive(channel.as_mut().unwrap(), &record) {
+                        Ok(Receipt::Stored { record, .. })
+                        | Ok(Receipt::Refused { record, .. }) => Message::Encrypted { record },
+                        Err(e) => Message::Error {
+                            message: e.to_string(),
+                        },
+                    }
+                }
+                other => panic!("unexpected {} message", other.name()),
+            };
+            protocol::send(&mut stream, &reply).unwrap();
+        }
+        secrets
+    })
+}
+
+/// The tenant's side of the connection to a simulated platform running the enclave, and
+/// the platform's thread.
+fn connect(
+    enclave: EnclaveIdentity,
+    tamper: bool,
+) -> (Attestation<UnixStream>, JoinHandle<SecretStore>) {
+    let qe = QuotingEnclave::new(Platform::default()).unwrap();
+    let policy = Policy::new(vec![EnclaveIdentity::default().mrenclave], Vec::new()).unwrap();
+    let collateral = common::collateral(&qe);
+
+    let (tenant_stream, platform_stream) = UnixStream::pair().unwrap();
+    let platform = simulated_platform(qe, enclave, platform_stream, tamper);
+
+    let mut attestation = Attestation::request(tenant_stream).unwrap();
+    attestation.verify(&collateral, &policy).unwrap();
+    (attestation, platform)
+}
+
+#[test]
+fn secrets_are_provisioned_to_verified_enclave() {
+    let (attestation, platform) = connect(EnclaveIdentity::default(), false);
+
+    let mut session = attestation.open_channel().unwrap();
+    session.provision("disk-key", &[0x5e; 32]).unwrap();
+    session
+        .provision("config", b"{\"endpoint\": \"db.internal\"}")
+        .unwrap();
+    drop(session);
+
+    let secrets = platform.join().unwrap();
+    assert_eq!(secrets.get("disk-key"), Some(&[0x5e; 32][..]));
+    assert_eq!(
+        secrets.get("config"),
+        Some(&b"{\"endpoint\": \"db.internal\"}"[..])
+    );
+}
+
+#[test]
+fn provisioned_secret_is_not_replaced() {
+    let (attestation, platform) = connect(EnclaveIdentity::default(), false);
+
+    let mut session = attestation.open_channel().unwrap();
+    session.provision("disk-key", &[0x5e; 32]).unwrap();
+    assert!(session.provision("disk-key", &[0x66; 32]).is_err());
+
+    // The channel is still usable after the refusal.
+    session.provision("config", b"{}").unwrap();
+    drop(session);
+
+    let secrets = platform.join().unwrap();
+    assert_eq!(secrets.get("disk-key"), Some(&[0x5e; 32][..]));
+    assert_eq!(secrets.get("config"), Some(&b"{}"[..]));
+}
+
+#[test]
+fn secrets_are_not_provisioned_to_unverified_enclave() {
+    let impostor = EnclaveIdentity {
+        mrenclave: [0x1d; 32],
+        ..Default::default()
+    };
+    let (attestation, platform) = connect(impostor, false);
+
+    // The tenant refuses to open the channel, so no secret can be sent.
+    assert!(attestation.open_channel().is_err());
+
+    let secrets = platform.join().unwrap();
+    assert_eq!(secrets.names().count(), 0);
+}
+
+#[test]
+fn secrets_tampered_with_in_transit_are_rejected() {
+    let (attestation, platform) = connect(EnclaveIdentity::default(), true);
+
+    let mut session = attestation.open_channel().unwrap();
+    assert!(session.provision("disk-key", &[0x5e; 32]).is_err());
+    drop(session);
+
+    let secrets = platform.join().unwrap();
+    assert_eq!(secrets.get("disk-key"), None);
+}
@@ -0,0 +1,114 @@
+//! RA-TLS certificate verification, against certificates that embed Quotes from the
+//! simulated Quoting Enclave of attestation-sim in the extension the enclave uses.
+
+mod common;
+
+use attestation_protocol::ra_tls;
+use attestation_sim::{EnclaveIdentity, Platform, QuotingEnclave};
+use attestation_tenant::{Policy, RaTlsVerifier};
+use openssl::asn1::{Asn1Object, Asn1OctetString, Asn1Time};
+use openssl::ec::{EcGroup, EcKey};
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{PKey, Private};
+use openssl::x509::{X509Builder, X509Extension, X509NameBuilder};
+
+/// A simulated enclave's Quoting Enclave, and a tenant trusting the enclave.
+struct Fixture {
+    qe: QuotingEnclave,
+    enclave: EnclaveIdentity,
+    verifier: RaTlsVerifier,
+}
+
+impl Fixture {
+    fn new() -> Self {
+        let qe = QuotingEnclave::new(Platform::default()).unwrap();
+        let enclave = EnclaveIdentity::default();
+        let policy = Policy::new(vec![enclave.mrenclave], Vec::new()).unwrap();
+        let verifier = RaTlsVerifier::new(common::collateral(&qe), policy);
+        Fixture {
+            qe,
+            enclave,
+            verifier,
+        }
+    }
+
+    /// A Quote of the enclave that binds the key.
+    fn quote(&self, key: &PKey<Private>) -> Vec<u8> {
+        let report_data = ra_tls::report_data(&key.public_key_to_der().unwrap());
+        let report = self
+            .enclave
+            .report(&self.qe.target_info(), &report_data)
+            .unwrap();
+        self.qe.quote(&report).unwrap()
+    }
+}
+
+fn key() -> PKey<Private> {
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+    PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap()
+}
+
+/// A self-signed certificate for the key, which carries the Quote, if any, in the RA-TLS
+/// extension.
+fn certificate(key: &PKey<Private>, quote: Option<&[u8]>) -> Vec<u8> {
+    let mut name = X509NameBuilder::new().unwrap();
+    name.append_entry_by_nid(Nid::COMMONNAME, "localhost")
+        .unwrap();
+    let name = name.build();
+
+    let mut builder = X509Builder::new().unwrap();
+    builder.set_version(2).unwrap();
+    builder.set_subject_name(&name).unwrap();
+    builder.set_issuer_name(&name).unwrap();
+    builder.set_pubkey(key).unwrap();
+    builder
+        .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+        .unwrap();
+    builder
+        .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+        .unwrap();
+    if let Some(quote) = quote {
+        let oid = ra_tls::QUOTE_EXTENSION_OID
+            .iter()
+            .map(|arc| arc.to_string())
+            .collect::<Vec<_>>()
+            .join(".");
+        let extension = X509Extension::new_from_der(
+            &Asn1Object::from_str(&oid).unwrap(),
+            false,
+            &Asn1OctetString::new_from_bytes(quote).unwrap(),
+        )
+        .unwrap();
+        builder.append_extension(extension).unwrap();
+    }
+    builder.sign(key, MessageDigest::sha256()).unwrap();
+    builder.build().to_der().unwrap()
+}
+
+#[test]
+fn quote_in_the_extension_is_verified() {
+    let fixture = Fixture::new();
+    let key = key();
+    let certificate = certificate(&key, Some(&fixture.quote(&key)));
+
+    let verification = fixture.verifier.verify_certificate(&certificate).unwrap();
+    assert!(verification.verified());
+}
+
+#[test]
+fn certificate_without_quote_is_rejected() {
+    let fixture = Fixture::new();
+    let certificate = certificate(&key(), None);
+    assert!(fixture.verifier.verify_certificate(&certificate).is_err());
+}
+
+#[test]
+fn quote_of_another_key_is_rejected() {
+    let fixture = Fixture::new();
+    let key = key();
+    let certificate = certificate(&key, Some(&fixture.quote(&self::key())));
+
+    let verification = fixture.verifier.verify_certificate(&certificate).unwrap();
+    assert!(!verification.verified());
+}
@@ -0,0 +1,339 @@
+//! Quote verification against Quotes from the simulated Quoting Enclave of attestation-sim,
+//! with deliberate defects: every check must fail on its defect, and only on its defect.
+
+mod common;
+
+use attestation_sim::{Credential, EnclaveIdentity, Platform, QuoteBuilder, QuotingEnclave};
+use attestation_tenant::{
+    Check, Collateral, Outcome, Policy, Quote, QuoteError, QuoteVerifier, TcbStatus, Verification,
+};
+use sgx_isa::AttributesFlags;
+
+const REPORT_DATA: [u8; 64] = [0x42; 64];
+
+/// A simulated platform, an enclave running on it and the tenant's collateral for it.
+struct Fixture {
+    qe: QuotingEnclave,
+    enclave: EnclaveIdentity,
+    collateral: Collateral,
+    policy: Policy,
+}
+
+impl Fixture {
+    fn new() -> Self {
+        let qe = QuotingEnclave::new(Platform::default()).unwrap();
+        let collateral = common::collateral(&qe);
+
+        let enclave = EnclaveIdentity::default();
+        let policy = Policy::new(vec![enclave.mrenclave], Vec::new()).unwrap();
+
+        Fixture {
+            qe,
+            enclave,
+            collateral,
+            policy,
+        }
+    }
+
+    /// A QuoteBuilder for a Quote of the enclave, which binds REPORT_DATA.
+    fn builder(&self) -> QuoteBuilder {
+        let report = self
+            .enclave
+            .report(&self.qe.target_info(), &REPORT_DATA)
+            .unwrap();
+        self.qe.quote_builder(&report).unwrap()
+    }
+
+    fn verify(&self, quote: &[u8]) -> Verification {
+        QuoteVerifier::new()
+            .expect_report_data(REPORT_DATA)
+            .verify(quote, &self.collateral, &self.policy)
+            .unwrap()
+    }
+
+    fn verify_builder(&self, builder: &QuoteBuilder) -> Verification {
+        self.verify(&builder.build().unwrap())
+    }
+}
+
+/// The checks that did not pass.
+fn failed(verification: &Verification) -> Vec<Check> {
+    verification
+        .checks
+        .iter()
+        .filter(|(_, outcome)| matches!(outcome, Outcome::Fail(_)))
+        .map(|(check, _)| *check)
+        .collect()
+}
+
+#[test]
+fn genuine_quote_is_verified() {
+    let fixture = Fixture::new();
+    let verification = fixture.verify_builder(&fixture.builder());
+
+    assert!(verification.verified(), "{}", verification);
+    assert_eq!(verification.identity.mrenclave, fixture.enclave.mrenclave);
+    assert_eq!(verification.tcb.unwrap().status, TcbStatus::UpToDate);
+    assert_eq!(verification.qe_tcb_status, Some(TcbStatus::UpToDate));
+    assert_eq!(
+        verification.platform.unwrap().fmspc,
+        fixture.qe.platform().fmspc
+    );
+}
+
+#[test]
+fn pck_from_another_issuer_is_rejected() {
+    let fixture = Fixture::new();
+    let rogue_root = Credential::issue("Rogue Root CA", None, true, 30, None).unwrap();
+    let rogue_ca = Credential::issue("Rogue PCK CA", Some(&rogue_root), true, 30, None).unwrap();
+    let extension = fixture.qe.platform().sgx_extension();
+    let pck = Credential::issue("Rogue PCK", Some(&rogue_ca), false, 30, Some(&extension)).unwrap();
+
+    let mut builder = fixture.builder();
+    builder
+        .set_pck(&pck, &[&rogue_ca.cert, &rogue_root.cert])
+        .unwrap();
+    let verification = fixture.verify_builder(&builder);
+
+    // The rogue CA issued no CRL the tenant trusts either.
+    assert_eq!(
+        failed(&verification),
+        vec![Check::PckChain, Check::Revocation]
+    );
+}
+
+#[test]
+fn expired_pck_is_rejected() {
+    let fixture = Fixture::new();
+    let pki = fixture.qe.pki();
+    let extension = fixture.qe.platform().sgx_extension();
+    let pck = Credential::issue(
+        "Expired PCK",
+        Some(&pki.pck_ca),
+        false,
+        -1,
+        Some(&extension),
+    )
+    .unwrap();
+
+    let mut builder = fixture.builder();
+    builder
+        .set_pck(&pck, &[&pki.pck_ca.cert, &pki.root.cert])
+        .unwrap();
+    let verification = fixture.verify_builder(&builder);
+
+    assert_eq!(failed(&verification), vec![Check::PckChain]);
+}
+
+#[test]
+fn revoked_pck_is_rejected() {
+    let mut fixture = Fixture::new();
+    let pki = fixture.qe.pki();
+    fixture.collateral.crls.clear();
+    let root_crl = pki.root.issue_crl(&[], 30).unwrap();
+    let pck_ca_crl = pki.pck_ca.issue_crl(&[&pki.pck.cert], 30).unwrap();
+    fixture.collateral.add_crl(&root_crl).unwrap();
+    fixture.collateral.add_crl(&pck_ca_crl).unwrap();
+
+    let verification = fixture.verify_builder(&fixture.builder());
+
+    assert_eq!(failed(&verification), vec![Check::Revocation]);
+}
+
+#[test]
+fn missing_crl_is_rejected() {
+    let mut fixture = Fixture::new();
+    fixture.collateral.crls.pop();
+
+    let verification = fixture.verify_builder(&fixture.builder());
+
+    assert_eq!(failed(&verification), vec![Check::Revocation]);
+}
+
+#[test]
+fn expired_crl_is_rejected() {
+    let mut fixture = Fixture::new();
+    let crl = fixture.qe.pki().pck_ca.issue_crl(&[], -1).unwrap();
+    fixture.collateral.crls.pop();
+    fixture.collateral.add_crl(&crl).unwrap();
+
+    let verification = fixture.verify_builder(&fixture.builder());
+
+    assert_eq!(failed(&verification), vec![Check::Revocation]);
+}
+
+#[test]
+fn missing_tcb_info_is_rejected() {
+    let mut fixture = Fixture::new();
+    fixture.collateral.tcb_info = None;
+
+    let verification = fixture.verify_builder(&fixture.builder());
+
+    assert_eq!(
+        failed(&verification),
+        vec![Check::TcbInfo, Check::TcbStatus]
+    );
+}
+
+#[test]
+fn tampered_tcb_info_is_rejected() {
+    let mut fixture = Fixture::new();
+    let tcb_info = fixture
+        .qe
+        .tcb_info()
+        .unwrap()
+        .replace("\"pcesvn\":9", "\"pcesvn\":8");
+    let tcb_chain = fixture.qe.pki().tcb_chain_pem().unwrap();
+    fixture
+        .collateral
+        .set_tcb_info(&tcb_info, &tcb_chain)
+        .unwrap();
+
+    let verification = fixture.verify_builder(&fixture.builder());
+
+    assert_eq!(
+        failed(&verification),
+        vec![Check::TcbInfo, Check::TcbStatus]
+    );
+}
+
+#[test]
+fn platform_tcb_status_outside_policy_is_rejected() {
+    let mut fixture = Fixture::new();
+    fixture.policy.allowed_tcb_statuses = vec![TcbStatus::SWHardeningNeeded];
+
+    let verification = fixture.verify_builder(&fixture.builder());
+
+    // The policy's TCB statuses apply to the Quoting Enclave's TCB level too.
+    assert_eq!(
+        failed(&verification),
+        vec![Check::TcbStatus, Check::QeIdentity]
+    );
+}
+
+#[test]
+fn bad_attestation_key_signature_is_rejected() {
+    let fixture = Fixture::new();
+    let mut builder = fixture.builder();
+    builder.defects.bad_isv_signature = true;
+
+    let verification = fixture.verify_builder(&builder);
+
+    assert_eq!(failed(&verification), vec![Check::AkSignature]);
+}
+
+#[test]
+fn tampered_report_is_rejected() {
+    let fixture = Fixture::new();
+    let mut quote = fixture.builder().build().unwrap();
+
+    // The ISVSVN of the ISV enclave Report, which follows the 48-byte header.
+    quote[48 + 258] ^= 0x01;
+    let verification = fixture.verify(&quote);
+
+    assert_eq!(failed(&verification), vec![Check::AkSignature]);
+}
+
+#[test]
+fn bad_qe_report_signature_is_rejected() {
+    let fixture = Fixture::new();
+    let mut builder = fixture.builder();
+    builder.defects.bad_qe_report_signature = true;
+
+    let verification = fixture.verify_builder(&builder);
+
+    assert_eq!(failed(&verification), vec![Check::QeReportSignature]);
+}
+
+#[test]
+fn wrong_qe_report_data_is_rejected() {
+    let fixture = Fixture::new();
+    let mut builder = fixture.builder();
+    builder.defects.wrong_qe_report_data = true;
+
+    let verification = fixture.verify_builder(&builder);
+
+    assert_eq!(failed(&verification), vec![Check::QeReportDataHash]);
+}
+
+#[test]
+fn impostor_quoting_enclave_is_rejected() {
+    let fixture = Fixture::new();
+    let mut builder = fixture.builder();
+    builder.qe_report.mrsigner = [0xbd; 32];
+
+    let verification = fixture.verify_builder(&builder);
+
+    assert_eq!(failed(&verification), vec![Check::QeIdentity]);
+}
+
+#[test]
+fn identity_of_another_enclave_is_rejected() {
+    let mut fixture = Fixture::new();
+    let tcb_chain = fixture.qe.pki().tcb_chain_pem().unwrap();
+
+    for id in &["QVE", "TD_QE"] {
+        let identity = fixture.qe.enclave_identity(id).unwrap();
+        assert!(fixture
+            .collateral
+            .set_qe_identity(&identity, &tcb_chain)
+            .is_err());
+    }
+}
+
+#[test]
+fn unexpected_report_data_is_rejected() {
+    let fixture = Fixture::new();
+    let quote = fixture.builder().build().unwrap();
+
+    let verification = QuoteVerifier::new()
+        .expect_report_data([0x24; 64])
+        .verify(&quote, &fixture.collateral, &fixture.policy)
+        .unwrap();
+
+    assert_eq!(failed(&verification), vec![Check::ReportData]);
+}
+
+#[test]
+fn unknown_enclave_is_rejected() {
+    let mut fixture = Fixture::new();
+    fixture.policy.mrenclave = vec![[0x0e; 32]];
+
+    let verification = fixture.verify_builder(&fixture.builder());
+
+    assert_eq!(failed(&verification), vec![Check::IdentityPolicy]);
+}
+
+#[test]
+fn debug_enclave_is_rejected() {
+    let mut fixture = Fixture::new();
+    fixture.enclave.attributes.flags |= AttributesFlags::DEBUG;
+
+    let verification = fixture.verify_builder(&fixture.builder());
+
+    assert_eq!(failed(&verification), vec![Check::IdentityPolicy]);
+}
+
+#[test]
+fn malformed_quotes_are_errors() {
+    let fixture = Fixture::new();
+    let quote = fixture.builder().build().unwrap();
+
+    for len in 0..quote.len() {
+        assert!(Quote::parse(&quote[..len]).is_err(), "length {}", len);
+        assert!(QuoteVerifier::new()
+            .verify(&quote[..len], &fixture.collateral, &fixture.policy)
+            .is_err());
+    }
+
+    let mut trailing = quote.clone();
+    trailing.push(0);
+    assert_eq!(
+        Quote::parse(&trailing).err(),
+        Some(QuoteError::TrailingData(1))
+    );
+
+    let mut version = quote.clone();
+    version[0] = 2;
+    assert_eq!(Quote::parse(&version).err(), Some(QuoteError::Version(2)));
+}